@@ -0,0 +1,178 @@
+//! Compares `CardSet` with the `Vec<Card>` hands it replaced, on the operations the engine
+//! does for every action: looking a card up, taking it out of a hand, checking a set of cards
+//! is held, and picking out a suit to follow.
+
+use std::hint::black_box;
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use prefsty::core::{
+    card_set::CardSet,
+    game::CardsInPlay,
+    types::{Card, CardSuit},
+};
+
+/// Deals that are the same on every run, so both representations see the same hands.
+fn deals() -> Vec<CardsInPlay> {
+    let deck: Vec<Card> = CardSet::DECK.iter().collect();
+    (0..64)
+        .map(|i| {
+            let mut cards = deck.clone();
+            cards.rotate_left(i % 32);
+            if i % 2 == 1 {
+                cards.reverse();
+            }
+            let hand = |n: usize| cards[n * 10..(n + 1) * 10].iter().copied().collect();
+            CardsInPlay {
+                hands: [hand(0), hand(1), hand(2)],
+                hidden: [cards[30], cards[31]],
+            }
+        })
+        .collect()
+}
+
+fn vec_hands(deals: &[CardsInPlay]) -> Vec<Vec<Card>> {
+    deals
+        .iter()
+        .flat_map(|deal| deal.hands)
+        .map(|hand| hand.iter().collect())
+        .collect()
+}
+
+fn set_hands(deals: &[CardsInPlay]) -> Vec<CardSet> {
+    deals.iter().flat_map(|deal| deal.hands).collect()
+}
+
+fn contains(c: &mut Criterion) {
+    let deals = deals();
+    let deck: Vec<Card> = CardSet::DECK.iter().collect();
+    let vecs = vec_hands(&deals);
+    let sets = set_hands(&deals);
+
+    let mut group = c.benchmark_group("contains");
+    group.bench_function("vec", |b| {
+        b.iter(|| {
+            let mut found = 0;
+            for hand in &vecs {
+                for card in &deck {
+                    found += black_box(hand).contains(card) as usize;
+                }
+            }
+            found
+        })
+    });
+    group.bench_function("card_set", |b| {
+        b.iter(|| {
+            let mut found = 0;
+            for hand in &sets {
+                for card in &deck {
+                    found += black_box(hand).contains(*card) as usize;
+                }
+            }
+            found
+        })
+    });
+    group.finish();
+}
+
+fn remove(c: &mut Criterion) {
+    let deals = deals();
+    let vecs = vec_hands(&deals);
+    let sets = set_hands(&deals);
+
+    // plays every hand out card by card, highest first
+    let mut group = c.benchmark_group("remove");
+    group.bench_function("vec", |b| {
+        b.iter(|| {
+            for hand in &vecs {
+                let mut hand = hand.clone();
+                for card in hand.clone().iter().rev() {
+                    let i = hand.iter().position(|c| c == card).unwrap();
+                    hand.remove(i);
+                }
+                black_box(hand);
+            }
+        })
+    });
+    group.bench_function("card_set", |b| {
+        b.iter(|| {
+            for hand in &sets {
+                let mut hand = *hand;
+                for card in hand.iter().rev() {
+                    hand.remove(card);
+                }
+                black_box(hand);
+            }
+        })
+    });
+    group.finish();
+}
+
+fn contains_all(c: &mut Criterion) {
+    let deals = deals();
+    let vecs = vec_hands(&deals);
+    let sets = set_hands(&deals);
+    // two cards to discard out of every hand, as in a talon exchange
+    let discards: Vec<Vec<Card>> = vecs.iter().map(|hand| hand[3..5].to_vec()).collect();
+
+    let mut group = c.benchmark_group("contains_all");
+    group.bench_function("vec", |b| {
+        b.iter(|| {
+            vecs.iter()
+                .zip(&discards)
+                .filter(|(hand, discard)| discard.iter().all(|card| hand.contains(card)))
+                .count()
+        })
+    });
+    group.bench_function("card_set", |b| {
+        b.iter(|| {
+            sets.iter()
+                .zip(&discards)
+                .filter(|(hand, discard)| {
+                    let discard: CardSet = discard.iter().copied().collect();
+                    hand.contains_all(discard)
+                })
+                .count()
+        })
+    });
+    group.finish();
+}
+
+fn in_suit(c: &mut Criterion) {
+    let deals = deals();
+    let vecs = vec_hands(&deals);
+    let sets = set_hands(&deals);
+
+    // the cards that can follow each suit led
+    let mut group = c.benchmark_group("in_suit");
+    group.bench_function("vec", |b| {
+        b.iter(|| {
+            let mut count = 0;
+            for hand in &vecs {
+                for suit in CardSuit::ALL {
+                    let in_suit: Vec<Card> = black_box(hand)
+                        .iter()
+                        .filter(|card| card.suit == suit)
+                        .copied()
+                        .collect();
+                    count += in_suit.len();
+                }
+            }
+            count
+        })
+    });
+    group.bench_function("card_set", |b| {
+        b.iter(|| {
+            let mut count = 0;
+            for hand in &sets {
+                for suit in CardSuit::ALL {
+                    count += black_box(hand).in_suit(suit).len();
+                }
+            }
+            count
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, contains, remove, contains_all, in_suit);
+criterion_main!(benches);
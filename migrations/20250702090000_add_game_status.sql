@@ -0,0 +1,13 @@
+CREATE TYPE game_status AS ENUM ('Waiting', 'InProgress', 'Paused', 'Finished', 'Abandoned');
+
+-- Games created before this were dealt straight away
+ALTER TABLE games
+    ADD COLUMN status game_status NOT NULL DEFAULT 'InProgress',
+    ADD COLUMN settings JSONB,
+    ADD COLUMN resigned SMALLINT,
+    ALTER COLUMN state DROP NOT NULL;
+ALTER TABLE games ALTER COLUMN status SET DEFAULT 'Waiting';
+
+ALTER TABLE joined
+    ADD COLUMN ready BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN abort_vote BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,2 @@
+-- Seat a player asked to swap into, waiting for the player sitting there to ask back
+ALTER TABLE joined ADD COLUMN swap_to SMALLINT;
@@ -0,0 +1,6 @@
+ALTER TABLE games
+    ADD COLUMN private BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN invite_code TEXT;
+
+-- Seat kept free for one user, who can take it without an invite
+ALTER TABLE joined ADD COLUMN reserved_for UUID REFERENCES users(id);
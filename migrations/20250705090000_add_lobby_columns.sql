@@ -0,0 +1,20 @@
+-- Lobby filters read these instead of the settings and state
+ALTER TABLE games
+    ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    ADD COLUMN seats SMALLINT NOT NULL DEFAULT 3,
+    ADD COLUMN ranked BOOLEAN NOT NULL DEFAULT FALSE;
+
+UPDATE games
+SET seats  = COALESCE(rules->>'seats', '3')::smallint,
+    ranked = COALESCE(rules->>'ranked', 'false')::boolean
+FROM (
+    SELECT id, COALESCE(
+        settings->'rules',
+        (SELECT value FROM jsonb_each(state) LIMIT 1)->'rules'
+    ) AS rules
+    FROM games
+) AS g
+WHERE games.id = g.id;
+
+CREATE INDEX games_lobby_idx ON games (status, created_at DESC);
+CREATE INDEX joined_user_idx ON joined (user_id);
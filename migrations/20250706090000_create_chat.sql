@@ -0,0 +1,19 @@
+CREATE TYPE chat_channel AS ENUM ('Table', 'Spectators');
+
+CREATE TABLE chat_messages (
+    id      BIGSERIAL    PRIMARY KEY,
+    game_id UUID         NOT NULL REFERENCES games(id),
+    user_id UUID         NOT NULL REFERENCES users(id),
+    channel chat_channel NOT NULL,
+    text    TEXT         NOT NULL,
+    sent_at TIMESTAMPTZ  NOT NULL DEFAULT now()
+);
+CREATE INDEX chat_messages_game_idx ON chat_messages (game_id, id);
+CREATE INDEX chat_messages_user_idx ON chat_messages (user_id, sent_at);
+
+-- Users whose chat `user_id` doesn't want to see
+CREATE TABLE mutes (
+    user_id  UUID NOT NULL REFERENCES users(id),
+    muted_id UUID NOT NULL REFERENCES users(id),
+    PRIMARY KEY (user_id, muted_id)
+);
@@ -0,0 +1,11 @@
+-- Websocket connections to games, on whichever server instance they are
+CREATE TABLE connections (
+    id       UUID        PRIMARY KEY,
+    instance UUID        NOT NULL,
+    game_id  UUID        NOT NULL REFERENCES games(id),
+    user_id  UUID        NOT NULL REFERENCES users(id),
+    away     BOOLEAN     NOT NULL DEFAULT FALSE,
+    seen_at  TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+CREATE INDEX connections_game_idx ON connections (game_id);
+CREATE INDEX connections_instance_idx ON connections (instance);
@@ -0,0 +1,2 @@
+-- Number of the last event published for the game, for clients to resume from
+ALTER TABLE games ADD COLUMN seq BIGINT NOT NULL DEFAULT 0;
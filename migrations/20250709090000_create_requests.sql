@@ -0,0 +1,11 @@
+-- Requests sent with an id over a websocket, remembered for a while so retries of them aren't
+-- applied twice
+CREATE TABLE requests (
+    user_id    UUID        NOT NULL REFERENCES users(id),
+    id         TEXT        NOT NULL,
+    done       BOOLEAN     NOT NULL DEFAULT FALSE,
+    error      TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (user_id, id)
+);
+CREATE INDEX requests_created_idx ON requests (created_at);
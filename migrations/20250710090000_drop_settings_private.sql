@@ -0,0 +1,2 @@
+-- Whether a game is private is kept in games.private alone
+UPDATE games SET settings = settings - 'private' WHERE settings ? 'private';
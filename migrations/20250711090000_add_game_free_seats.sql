@@ -0,0 +1,9 @@
+-- Kept up to date as players join and leave, so the lobby can filter on it
+ALTER TABLE games ADD COLUMN free_seats SMALLINT NOT NULL DEFAULT 0;
+
+UPDATE games
+SET free_seats = (
+    SELECT COUNT(*) FROM joined WHERE joined.game_id = games.id AND joined.user_id IS NULL
+);
+
+CREATE INDEX games_free_seats_idx ON games (free_seats);
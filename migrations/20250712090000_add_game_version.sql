@@ -0,0 +1,2 @@
+-- Bumped on every saved state, so a state worked out from an older one isn't saved over a newer
+ALTER TABLE games ADD COLUMN version INTEGER NOT NULL DEFAULT 0;
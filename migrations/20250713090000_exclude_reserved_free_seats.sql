@@ -0,0 +1,6 @@
+-- Seats kept for someone aren't free to anyone else in the lobby
+UPDATE games
+SET free_seats = (
+    SELECT COUNT(*) FROM joined
+    WHERE joined.game_id = games.id AND joined.user_id IS NULL AND joined.reserved_for IS NULL
+);
@@ -1,5 +1,13 @@
 pub mod actions;
+pub mod all_pass;
+pub mod card_set;
 pub mod game;
+pub mod ledger;
+pub mod notation;
+pub mod record;
+pub mod ruleset;
+pub mod settlement;
+mod solver;
 pub mod types;
 
 pub mod bidding;
@@ -1,6 +1,7 @@
 use super::types::{Card, GameContract};
 use serde::{Deserialize, Serialize};
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GameAction {
     pub player: usize,
     pub kind: GameActionKind,
@@ -12,7 +13,7 @@ impl GameAction {
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum GameActionKind {
     Bid,
     PassBid,
@@ -26,9 +27,23 @@ pub enum GameActionKind {
     DeclareContre,
     PassHelpContre,
     PlayCard(Card),
+    /// A defender lays their cards face up on the table.
+    LayOpen,
+    /// Calls out a revoke by the other side in a finished trick. Honor mode only.
+    CallRevoke,
+    /// Accepts the tricks as played once the last one is in. Honor mode only.
+    ConfirmHand,
+    /// The declarer claims `tricks` of the tricks left, ending the hand if the others agree.
+    Claim {
+        tricks: u32,
+    },
+    /// Gives the other side every trick left, ending the hand if the others agree.
+    Concede,
+    AcceptClaim,
+    RejectClaim,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CardChoice {
     pub take_cards: Vec<Card>,
     pub discard_cards: Vec<Card>,
@@ -0,0 +1,332 @@
+use serde::{Deserialize, Serialize};
+
+use super::{
+    actions::{GameAction, GameActionKind},
+    bidding::bidding::BiddingState,
+    game::{Game, GameError, GameState, turn_inc},
+    ledger::LedgerEntry,
+    playing::RoundState,
+    types::Card,
+};
+
+/// Bula written for every trick taken in an all-pass play-out.
+pub const TRICK_PENALTY: u32 = 2;
+
+const TALON_TRICKS: u32 = 2;
+const TOTAL_TRICKS: u32 = 10;
+
+/*
+   Play-out of a hand everyone passed, with each player trying to take as few tricks as
+   possible. There are no trumps and suit has to be followed.
+
+   The talon cards are turned up one at a time and set the suit of the first two tricks. They
+   can't win, so a talon trick nobody followed goes to nobody. The first hand starts both talon
+   tricks; after that whoever took the last trick leads, or the first hand again when nobody did.
+   A hand played out writes a refa once it's over, like one dealt again.
+*/
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AllPassState {
+    tricks: [u32; 3],
+    tricks_played: u32,
+    /// Talon card turned up for the current trick, if it is a talon trick.
+    turned: Option<Card>,
+    round: RoundState,
+}
+
+impl AllPassState {
+    pub fn tricks(&self) -> &[u32; 3] {
+        &self.tricks
+    }
+
+    /// Tricks finished so far, including talon tricks nobody took.
+    pub fn tricks_played(&self) -> u32 {
+        self.tricks_played
+    }
+
+    pub fn turned(&self) -> Option<Card> {
+        self.turned
+    }
+
+    fn is_talon_trick(&self) -> bool {
+        self.tricks_played < TALON_TRICKS
+    }
+}
+
+impl Game<AllPassState> {
+    pub fn apply(self, action: GameAction) -> Result<GameState, GameError> {
+        self.validate(&action)?;
+
+        match action.kind {
+            GameActionKind::PlayCard(card) => Ok(self.play_card(card)),
+            _ => Err(GameError::InvalidAction),
+        }
+    }
+
+    fn validate(&self, action: &GameAction) -> Result<(), GameError> {
+        self.validate_turn(action)?;
+
+        match action.kind {
+            GameActionKind::PlayCard(card) => self.validate_play_card(action.player, card),
+            _ => Err(GameError::InvalidAction),
+        }
+    }
+
+    fn validate_play_card(&self, player: usize, card: Card) -> Result<(), GameError> {
+        let hand = self.cards.hands[player];
+        if !hand.contains(card) {
+            return Err(GameError::BadAction);
+        }
+
+        match self.state.round.lead_suit() {
+            Some(suit) if card.suit != suit && hand.has_suit(suit) => Err(GameError::BadAction),
+            _ => Ok(()),
+        }
+    }
+
+    fn play_card(mut self, card: Card) -> GameState {
+        self.state.round = self.state.round.play_card(card, self.turn);
+        self.cards.hands[self.turn].remove(card);
+
+        if !self.state.round.is_round_over() {
+            self.turn = turn_inc(self.turn);
+            return GameState::AllPass(self);
+        }
+
+        self.end_round();
+        if self.state.tricks_played == TOTAL_TRICKS {
+            self.compute_scores();
+            // nobody played a contract, the same as a hand passed out and dealt again
+            if self.refas.has_refas_left() {
+                self.refas.add_active_refa(self.rules.seats);
+            }
+            GameState::Bidding(self.into_next_hand())
+        } else {
+            GameState::AllPass(self)
+        }
+    }
+
+    fn end_round(&mut self) {
+        let suit = self.state.round.lead_suit().expect("Lead suit always set");
+        let winner = self.state.round.highest_in_suit(suit);
+
+        if let Some(winner) = winner {
+            self.state.tricks[winner] += 1;
+        }
+        self.state.tricks_played += 1;
+
+        self.turn = match winner {
+            Some(winner) if !self.state.is_talon_trick() => winner,
+            _ => self.first,
+        };
+        self.start_round();
+    }
+
+    /// Clears the table, turning up the next talon card while there are talon tricks left.
+    fn start_round(&mut self) {
+        self.state.turned = self
+            .state
+            .is_talon_trick()
+            .then(|| self.cards.hidden[self.state.tricks_played as usize]);
+
+        self.state.round = match self.state.turned {
+            Some(card) => RoundState::led_with(card.suit),
+            None => RoundState::default(),
+        };
+    }
+
+    fn compute_scores(&mut self) {
+        let score_before = self.score.clone();
+
+        for position in 0..3 {
+            let seat = self.seat_of(position);
+            self.score[seat].add_bulls(self.state.tricks[position] * TRICK_PENALTY);
+        }
+
+        let entry = LedgerEntry::all_pass(self.hand);
+        self.ledger
+            .push(entry.with_changes(&score_before, &self.score));
+    }
+}
+
+impl From<Game<BiddingState>> for Game<AllPassState> {
+    fn from(prev: Game<BiddingState>) -> Self {
+        let mut game = Self {
+            state: AllPassState {
+                tricks: Default::default(),
+                tricks_played: 0,
+                turned: None,
+                round: RoundState::default(),
+            },
+            rules: prev.rules,
+            dealer: prev.dealer,
+            first: prev.first,
+            hand: prev.hand,
+            turn: prev.first,
+            cards: prev.cards,
+            score: prev.score,
+            refas: prev.refas,
+            ledger: prev.ledger,
+            plays: prev.plays,
+        };
+
+        game.start_round();
+        game
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{
+        game::{CardsInPlay, Refas, new_game},
+        ruleset::{AllPass, Ruleset},
+    };
+
+    /// Position 1 takes the first talon trick and position 2 the second.
+    const DEAL: &str = "♠AKQJ10 ♦98 ♥8 ♣AK / ♠987 ♦QJ10 ♥KQJ ♣Q / ♠- ♦AK ♥A109 ♣J10987 / 7♥ 7♦";
+
+    fn card(s: &str) -> Card {
+        s.parse().unwrap()
+    }
+
+    /// Deals `DEAL` at a table of `seats` and has everyone pass.
+    fn all_passed(seats: usize) -> Game<AllPassState> {
+        let rules = Ruleset {
+            seats,
+            all_pass: AllPass::PlayOut,
+            ..Ruleset::default()
+        };
+        let GameState::Bidding(mut game) = new_game(0, 30, 0, rules) else {
+            unreachable!()
+        };
+        game.cards = DEAL.parse::<CardsInPlay>().unwrap();
+
+        let mut state = GameState::Bidding(game);
+        for _ in 0..3 {
+            let GameState::Bidding(game) = state else {
+                panic!("Everyone passes");
+            };
+            let action = GameAction::new(game.turn, GameActionKind::PassBid);
+            state = game.apply(action).unwrap();
+        }
+
+        match state {
+            GameState::AllPass(game) => game,
+            _ => panic!("The hand is played out"),
+        }
+    }
+
+    fn play(game: Game<AllPassState>, card: &str) -> Game<AllPassState> {
+        let action = GameAction::new(game.turn, GameActionKind::PlayCard(self::card(card)));
+        match game.apply(action).unwrap() {
+            GameState::AllPass(game) => game,
+            _ => panic!("The hand goes on"),
+        }
+    }
+
+    #[test]
+    fn talon_cards_lead_the_first_two_tricks() {
+        let game = all_passed(3);
+        assert_eq!(game.state.turned(), Some(card("7♥")));
+        assert_eq!(game.turn, game.first);
+
+        let game = play(game, "8♥");
+        // Hearts were led by the talon, so they have to be followed.
+        let revoke = GameAction::new(game.turn, GameActionKind::PlayCard(card("Q♣")));
+        assert!(matches!(game.validate(&revoke), Err(GameError::BadAction)));
+        let game = play(play(game, "J♥"), "9♥");
+
+        // Position 1 took it, but the first hand starts the second talon trick too.
+        assert_eq!(game.state.tricks(), &[0, 1, 0]);
+        assert_eq!(game.state.turned(), Some(card("7♦")));
+        assert_eq!(game.turn, game.first);
+
+        let game = play(play(play(game, "9♦"), "10♦"), "K♦");
+
+        // Past the talon the last trick's taker leads whatever they like.
+        assert_eq!(game.state.tricks(), &[0, 1, 1]);
+        assert_eq!(game.state.tricks_played(), 2);
+        assert_eq!(game.state.turned(), None);
+        assert_eq!(game.turn, 2);
+    }
+
+    #[test]
+    fn every_trick_taken_costs_the_penalty() {
+        let mut game = all_passed(3);
+        game.state.tricks = [4, 0, 6];
+        game.compute_scores();
+
+        let bulls: Vec<i32> = game.score.iter().map(|score| score.bulls()).collect();
+        assert_eq!(
+            bulls,
+            vec![
+                30 + 4 * TRICK_PENALTY as i32,
+                30,
+                30 + 6 * TRICK_PENALTY as i32
+            ]
+        );
+        assert!(game.score.iter().all(|score| score.total_soups() == 0));
+    }
+
+    #[test]
+    fn the_dealer_sitting_out_pays_no_penalty() {
+        let mut game = all_passed(4);
+        let dealer = game.dealer;
+        game.state.tricks = [3, 3, 4];
+        game.compute_scores();
+
+        for seat in 0..4 {
+            let tricks = game
+                .position_of(seat)
+                .map_or(0, |position| game.state.tricks[position]);
+            assert_eq!(
+                game.score[seat].bulls(),
+                30 + (tricks * TRICK_PENALTY) as i32
+            );
+        }
+        assert_eq!(game.score[dealer].bulls(), 30);
+    }
+
+    /// Has everyone play the first card they're allowed to until the hand is over.
+    fn play_out(game: Game<AllPassState>) -> Game<BiddingState> {
+        let mut state = GameState::AllPass(game);
+        while let GameState::AllPass(game) = state {
+            let card = game.cards.hands[game.turn]
+                .iter()
+                .find(|&card| game.validate_play_card(game.turn, card).is_ok())
+                .unwrap();
+            let action = GameAction::new(game.turn, GameActionKind::PlayCard(card));
+            state = game.apply(action).unwrap();
+        }
+
+        match state {
+            GameState::Bidding(game) => game,
+            _ => panic!("The next hand is dealt"),
+        }
+    }
+
+    #[test]
+    fn the_hand_ends_after_ten_tricks() {
+        let game = play_out(all_passed(3));
+        // No talon trick went untaken, so all ten cost bula.
+        let added: i32 = game.score.iter().map(|score| score.bulls() - 30).sum();
+        assert_eq!(added, 10 * TRICK_PENALTY as i32);
+        assert_eq!(game.hand, 2);
+    }
+
+    #[test]
+    fn a_hand_played_out_writes_a_refa() {
+        let mut game = all_passed(3);
+        game.refas = Refas::new(1);
+
+        let game = play_out(game);
+        assert!((0..3).all(|seat| game.refas.has_active_refa(seat)));
+        assert!(!game.refas.has_refas_left());
+        assert!(!game.ledger.entries().last().unwrap().refa);
+
+        // With none left to write, the next one played out doesn't
+        let mut game = all_passed(3);
+        game.refas = Refas::new(0);
+        assert!(!play_out(game).refas.is_any_active());
+    }
+}
@@ -2,6 +2,8 @@ use crate::core::{
     actions::{GameAction, GameActionKind},
     choosing::ChoosingCardsState,
     game::{CardsInPlay, Game, GameError, GameState, PlayerScore, Refas},
+    ledger::ScoreLedger,
+    ruleset::{AllPass, Ruleset},
     types::GameContract,
 };
 
@@ -21,33 +23,56 @@ pub struct BiddingState {
 }
 
 impl Game<BiddingState> {
-    pub fn new(first: usize, starting_score: u32, num_refas: usize) -> Self {
+    /// Starts a game where the player in seat `first` opens the bidding of the first hand.
+    pub fn new(first: usize, starting_score: u32, num_refas: usize, rules: Ruleset) -> Self {
         <Game<BiddingState>>::new_starting_state(
-            first,
-            [
-                PlayerScore::new(starting_score),
-                PlayerScore::new(starting_score),
-                PlayerScore::new(starting_score),
-            ],
+            rules,
+            rules.dealer_before(first),
+            1,
+            vec![PlayerScore::new(starting_score, rules.seats); rules.seats],
             Refas::new(num_refas),
+            ScoreLedger::default(),
         )
     }
 
-    pub fn new_starting_state(first: usize, score: [PlayerScore; 3], refas: Refas) -> Self {
+    pub fn new_starting_state(
+        rules: Ruleset,
+        dealer: usize,
+        hand: usize,
+        score: Vec<PlayerScore>,
+        refas: Refas,
+        ledger: ScoreLedger,
+    ) -> Self {
+        let first = rules.first_position(dealer);
+
         Self {
             state: BiddingState {
                 bid: None,
                 can_steal_bid: false,
                 player_states: Default::default(),
             },
+            rules,
+            dealer,
             first,
+            hand,
             turn: first,
             cards: CardsInPlay::deal_random(),
             score,
             refas,
+            ledger,
+            plays: Vec::new(),
         }
     }
 
+    pub fn is_hand_start(&self) -> bool {
+        self.state.bid.is_none()
+            && self
+                .state
+                .player_states
+                .iter()
+                .all(|&x| x == PlayerBidState::NoBid)
+    }
+
     pub fn apply(self, action: GameAction) -> Result<GameState, GameError> {
         self.validate(&action)?;
 
@@ -146,7 +171,7 @@ impl Game<BiddingState> {
         match passed_players {
             0 | 1 => self.to_next_bidding_state(),
             2 => self.to_choosing_cards(),
-            _ => self.to_new_hand(),
+            _ => self.to_all_passed(),
         }
     }
 
@@ -179,12 +204,20 @@ impl Game<BiddingState> {
         GameState::ChoosingCards(self.into())
     }
 
-    fn to_new_hand(self) -> GameState {
-        GameState::Bidding(<Game<BiddingState>>::new_starting_state(
-            self.first + 1,
-            self.score,
-            self.refas,
-        ))
+    fn to_all_passed(self) -> GameState {
+        match self.rules.all_pass {
+            AllPass::Redeal => self.to_new_hand(),
+            AllPass::PlayOut => GameState::AllPass(self.into()),
+        }
+    }
+
+    /// Passing a hand out writes a refa for everyone while there are some left.
+    fn to_new_hand(mut self) -> GameState {
+        if self.refas.has_refas_left() {
+            self.refas.add_active_refa(self.rules.seats);
+        }
+
+        GameState::Bidding(self.into_next_hand())
     }
 
     fn claim_no_bid(mut self) -> GameState {
@@ -213,17 +246,37 @@ impl Game<BiddingState> {
     }
 }
 
+impl<S> Game<S> {
+    /// Passes the deal to the left and deals the next hand, carrying over everything that
+    /// outlives a single hand. Every transition to a new hand goes through here.
+    pub fn into_next_hand(self) -> Game<BiddingState> {
+        <Game<BiddingState>>::new_starting_state(
+            self.rules,
+            self.rules.next_dealer(self.dealer),
+            self.hand + 1,
+            self.score,
+            self.refas,
+            self.ledger,
+        )
+    }
+}
+
 impl From<Game<BiddingState>> for Game<ChoosingCardsState> {
     fn from(prev: Game<BiddingState>) -> Self {
         let bid = prev.state.bid.unwrap();
 
         Self {
             state: ChoosingCardsState::new(bid.value),
+            rules: prev.rules,
+            dealer: prev.dealer,
             first: prev.first,
+            hand: prev.hand,
             turn: bid.bidder,
             cards: prev.cards,
             score: prev.score,
             refas: prev.refas,
+            ledger: prev.ledger,
+            plays: prev.plays,
         }
     }
 }
@@ -235,11 +288,16 @@ impl From<Game<BiddingState>> for Game<NoBidClaimState> {
 
         Self {
             state: NoBidClaimState::new(bids_turned_to_passes),
+            rules: prev.rules,
+            dealer: prev.dealer,
             first: prev.first,
+            hand: prev.hand,
             turn: next_turn,
             cards: prev.cards,
             score: prev.score,
             refas: prev.refas,
+            ledger: prev.ledger,
+            plays: prev.plays,
         }
     }
 }
@@ -248,11 +306,16 @@ impl From<Game<BiddingState>> for Game<NoBidChoiceState> {
     fn from(prev: Game<BiddingState>) -> Self {
         Self {
             state: NoBidChoiceState::new(None, 1),
+            rules: prev.rules,
+            dealer: prev.dealer,
             first: prev.first,
+            hand: prev.hand,
             turn: prev.turn,
             cards: prev.cards,
             score: prev.score,
             refas: prev.refas,
+            ledger: prev.ledger,
+            plays: prev.plays,
         }
     }
 }
@@ -113,11 +113,16 @@ impl From<Game<NoBidClaimState>> for Game<NoBidChoiceState> {
 
         Self {
             state: NoBidChoiceState { bid: None, claims },
+            rules: prev.rules,
+            dealer: prev.dealer,
             first: prev.first,
+            hand: prev.hand,
             turn: next_turn,
             cards: prev.cards,
             score: prev.score,
             refas: prev.refas,
+            ledger: prev.ledger,
+            plays: prev.plays,
         }
     }
 }
@@ -218,11 +223,16 @@ impl From<Game<NoBidChoiceState>> for Game<RespondingToContractState> {
                 },
                 declarer,
             ),
+            rules: prev.rules,
+            dealer: prev.dealer,
             first: prev.first,
+            hand: prev.hand,
             turn: prev.turn,
             cards: prev.cards,
             score: prev.score,
             refas: prev.refas,
+            ledger: prev.ledger,
+            plays: prev.plays,
         }
     }
 }
@@ -0,0 +1,185 @@
+use std::ops::{BitAnd, BitOr, Sub};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::types::{Card, CardSuit, CardValue};
+
+/// A set of cards backed by a 32-bit mask, one bit per card of the deck.
+///
+/// Bits are laid out suit by suit in `CardSuit` order, eight bits per suit from seven up to
+/// ace, so a single suit is a contiguous byte. It serializes as a plain list of cards, the
+/// same shape hands always had.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CardSet(u32);
+
+impl CardSet {
+    pub const EMPTY: CardSet = CardSet(0);
+    pub const DECK: CardSet = CardSet(u32::MAX);
+
+    fn bit(card: Card) -> u32 {
+        1 << (card.suit as u32 * 8 + card.value as u32)
+    }
+
+    fn card_at(index: u32) -> Card {
+        Card {
+            suit: CardSuit::ALL[(index / 8) as usize],
+            value: CardValue::ALL[(index % 8) as usize],
+        }
+    }
+
+    /// All eight cards of a suit.
+    pub fn suit(suit: CardSuit) -> CardSet {
+        CardSet(0xFF << (suit as u32 * 8))
+    }
+
+    pub fn contains(&self, card: Card) -> bool {
+        self.0 & Self::bit(card) != 0
+    }
+
+    pub fn contains_all(&self, other: CardSet) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Adds a card, returning whether it was not already in the set.
+    pub fn insert(&mut self, card: Card) -> bool {
+        let missing = !self.contains(card);
+        self.0 |= Self::bit(card);
+        missing
+    }
+
+    /// Removes a card, returning whether it was in the set.
+    pub fn remove(&mut self, card: Card) -> bool {
+        let present = self.contains(card);
+        self.0 &= !Self::bit(card);
+        present
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Cards of this set in the given suit.
+    pub fn in_suit(&self, suit: CardSuit) -> CardSet {
+        *self & CardSet::suit(suit)
+    }
+
+    pub fn has_suit(&self, suit: CardSuit) -> bool {
+        !self.in_suit(suit).is_empty()
+    }
+
+    /// Last card in iteration order, which is the highest one when the set is a single suit.
+    pub fn highest(&self) -> Option<Card> {
+        (!self.is_empty()).then(|| Self::card_at(31 - self.0.leading_zeros()))
+    }
+
+    /// First card in iteration order, which is the lowest one when the set is a single suit.
+    pub fn lowest(&self) -> Option<Card> {
+        (!self.is_empty()).then(|| Self::card_at(self.0.trailing_zeros()))
+    }
+
+    /// Iterates cards suit by suit, lowest value first.
+    pub fn iter(&self) -> CardSetIter {
+        CardSetIter(self.0)
+    }
+}
+
+pub struct CardSetIter(u32);
+
+impl Iterator for CardSetIter {
+    type Item = Card;
+
+    fn next(&mut self) -> Option<Card> {
+        if self.0 == 0 {
+            return None;
+        }
+
+        let index = self.0.trailing_zeros();
+        self.0 &= self.0 - 1;
+        Some(CardSet::card_at(index))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.0.count_ones() as usize;
+        (len, Some(len))
+    }
+}
+
+impl DoubleEndedIterator for CardSetIter {
+    fn next_back(&mut self) -> Option<Card> {
+        if self.0 == 0 {
+            return None;
+        }
+
+        let index = 31 - self.0.leading_zeros();
+        self.0 &= !(1 << index);
+        Some(CardSet::card_at(index))
+    }
+}
+
+impl ExactSizeIterator for CardSetIter {}
+
+impl IntoIterator for CardSet {
+    type Item = Card;
+    type IntoIter = CardSetIter;
+
+    fn into_iter(self) -> CardSetIter {
+        self.iter()
+    }
+}
+
+impl FromIterator<Card> for CardSet {
+    fn from_iter<I: IntoIterator<Item = Card>>(iter: I) -> Self {
+        let mut set = CardSet::EMPTY;
+        set.extend(iter);
+        set
+    }
+}
+
+impl Extend<Card> for CardSet {
+    fn extend<I: IntoIterator<Item = Card>>(&mut self, iter: I) {
+        for card in iter {
+            self.insert(card);
+        }
+    }
+}
+
+impl BitOr for CardSet {
+    type Output = CardSet;
+
+    fn bitor(self, rhs: CardSet) -> CardSet {
+        CardSet(self.0 | rhs.0)
+    }
+}
+
+impl BitAnd for CardSet {
+    type Output = CardSet;
+
+    fn bitand(self, rhs: CardSet) -> CardSet {
+        CardSet(self.0 & rhs.0)
+    }
+}
+
+impl Sub for CardSet {
+    type Output = CardSet;
+
+    fn sub(self, rhs: CardSet) -> CardSet {
+        CardSet(self.0 & !rhs.0)
+    }
+}
+
+impl Serialize for CardSet {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de> Deserialize<'de> for CardSet {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let cards = Vec::<Card>::deserialize(deserializer)?;
+        Ok(cards.into_iter().collect())
+    }
+}
@@ -1,7 +1,9 @@
 use super::{
     actions::{CardChoice, GameAction, GameActionKind},
     bidding::bidding::BiddingState,
+    card_set::CardSet,
     game::{Game, GameError, GameState, get_third, turn_inc},
+    ledger::LedgerEntry,
     playing::PlayingState,
     types::{Card, GameContract, GameContractData, GameContractKind},
 };
@@ -39,7 +41,7 @@ impl Game<ChoosingCardsState> {
     }
 
     fn validate_choose_cards(&self, choice: &CardChoice) -> Result<(), GameError> {
-        if choice.take_cards.len() == choice.discard_cards.len() {
+        if choice.take_cards.len() != choice.discard_cards.len() {
             return Err(GameError::BadAction);
         }
 
@@ -55,42 +57,33 @@ impl Game<ChoosingCardsState> {
     }
 
     fn hidden_cards_contain_take(&self, take_cards: &[Card]) -> bool {
-        Self::is_cards_contained(&self.cards.hidden, take_cards)
+        let hidden: CardSet = self.cards.hidden.into_iter().collect();
+        Self::is_cards_contained(hidden, take_cards)
     }
 
     fn hand_cards_contain_discard(&self, discard_cards: &[Card]) -> bool {
         let current_player = self.turn;
-        Self::is_cards_contained(&self.cards.hands[current_player], discard_cards)
+        Self::is_cards_contained(self.cards.hands[current_player], discard_cards)
     }
 
-    fn is_cards_contained(container: &[Card], searched: &[Card]) -> bool {
-        for card in searched {
-            if !container.contains(card) {
-                return false;
-            }
-        }
+    fn is_cards_contained(container: CardSet, searched: &[Card]) -> bool {
+        let searched_set: CardSet = searched.iter().copied().collect();
 
-        true
+        // Comparing lengths rejects the same card being listed twice
+        searched_set.len() == searched.len() && container.contains_all(searched_set)
     }
 
     pub fn take_chosen_cards(mut self, choice: CardChoice) -> GameState {
         let current_player_hand = &mut self.cards.hands[self.turn];
-        *current_player_hand = Self::remove_cards(current_player_hand, &choice.discard_cards);
+        for card in choice.discard_cards {
+            current_player_hand.remove(card);
+        }
 
-        current_player_hand.extend_from_slice(&choice.take_cards);
+        current_player_hand.extend(choice.take_cards);
 
         self.to_choose_contract()
     }
 
-    fn remove_cards(container: &[Card], cards_to_remove: &[Card]) -> Vec<Card> {
-        let mut vec_container = container.to_vec();
-        for card in cards_to_remove {
-            let card_pos = container.iter().position(|c| c == card).unwrap();
-            vec_container.swap_remove(card_pos);
-        }
-        vec_container
-    }
-
     fn to_choose_contract(self) -> GameState {
         GameState::ChoosingContract(self.into())
     }
@@ -106,11 +99,16 @@ impl From<Game<ChoosingCardsState>> for Game<ChoosingContractState> {
             state: ChoosingContractState {
                 contract_bid: prev.state.contract_bid,
             },
+            rules: prev.rules,
+            dealer: prev.dealer,
             first: prev.first,
+            hand: prev.hand,
             turn: prev.turn,
             cards: prev.cards,
             score: prev.score,
             refas: prev.refas,
+            ledger: prev.ledger,
+            plays: prev.plays,
         }
     }
 }
@@ -167,11 +165,16 @@ impl From<Game<ChoosingContractState>> for Game<RespondingToContractState> {
                 declarer: prev.turn,
                 player_responses: Default::default(),
             },
+            rules: prev.rules,
+            dealer: prev.dealer,
             first: prev.first,
+            hand: prev.hand,
             turn: next_turn,
             cards: prev.cards,
             score: prev.score,
             refas: prev.refas,
+            ledger: prev.ledger,
+            plays: prev.plays,
         }
     }
 }
@@ -286,11 +289,20 @@ impl Game<RespondingToContractState> {
     }
 
     fn to_new_hand(mut self) -> GameState {
-        self.score[self.state.declarer].apply_result(
+        let declarer = self.seat_of(self.state.declarer);
+        let score_before = self.score.clone();
+        self.score[declarer].apply_result(self.state.contract, true, ContreLevel::NoContre);
+        let refa = self.play_refa(declarer, &score_before);
+
+        let entry = LedgerEntry::new(
+            self.hand,
+            declarer,
             self.state.contract,
-            true,
             ContreLevel::NoContre,
+            refa,
         );
+        self.ledger
+            .push(entry.with_changes(&score_before, &self.score));
 
         GameState::Bidding(self.into())
     }
@@ -306,18 +318,23 @@ impl From<Game<RespondingToContractState>> for Game<HelpOrContreToContractState>
                 declarer: prev.state.declarer,
                 player_responses: prev.state.player_responses,
             },
+            rules: prev.rules,
+            dealer: prev.dealer,
             first: prev.first,
+            hand: prev.hand,
             turn: next_turn,
             cards: prev.cards,
             score: prev.score,
             refas: prev.refas,
+            ledger: prev.ledger,
+            plays: prev.plays,
         }
     }
 }
 
 impl From<Game<RespondingToContractState>> for Game<BiddingState> {
     fn from(prev: Game<RespondingToContractState>) -> Self {
-        <Game<BiddingState>>::new_starting_state(turn_inc(prev.first), prev.score, prev.refas)
+        prev.into_next_hand()
     }
 }
 
@@ -406,11 +423,16 @@ impl From<Game<HelpOrContreToContractState>> for Game<ContreDeclaredState> {
                 contre_level: ContreLevel::Contre,
                 player_responses: prev.state.player_responses,
             },
+            rules: prev.rules,
+            dealer: prev.dealer,
             first: prev.first,
+            hand: prev.hand,
             turn: prev.state.declarer,
             cards: prev.cards,
             score: prev.score,
             refas: prev.refas,
+            ledger: prev.ledger,
+            plays: prev.plays,
         }
     }
 }
@@ -430,11 +452,16 @@ impl From<Game<HelpOrContreToContractState>> for Game<PlayingState> {
                 prev.state.declarer,
                 prev.state.player_responses,
             ),
+            rules: prev.rules,
+            dealer: prev.dealer,
             first: prev.first,
+            hand: prev.hand,
             turn: turn,
             cards: prev.cards,
             score: prev.score,
             refas: prev.refas,
+            ledger: prev.ledger,
+            plays: prev.plays,
         }
     }
 }
@@ -553,11 +580,16 @@ impl From<Game<ContreDeclaredState>> for Game<PlayingState> {
                 prev.state.declarer,
                 prev.state.player_responses,
             ),
+            rules: prev.rules,
+            dealer: prev.dealer,
             first: prev.first,
+            hand: prev.hand,
             turn: turn,
             cards: prev.cards,
             score: prev.score,
             refas: prev.refas,
+            ledger: prev.ledger,
+            plays: prev.plays,
         }
     }
 }
@@ -1,35 +1,92 @@
 use rand::seq::SliceRandom;
-use sqlx::{
-    Decode, Encode, Postgres, Type,
-    postgres::{PgTypeInfo, PgValueRef},
-};
 use std::collections::VecDeque;
 
 use super::{
     actions::GameAction,
+    all_pass::AllPassState,
     bidding::{
         bidding::BiddingState,
         no_bid::{NoBidChoiceState, NoBidClaimState},
     },
+    card_set::CardSet,
     choosing::*,
+    ledger::ScoreLedger,
     playing::*,
-    types::{Card, CardSuit, CardValue, GameContractData, GameContractKind},
+    ruleset::Ruleset,
+    types::{Card, GameContractData, GameContractKind},
 };
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 
 pub struct Room {
     pub game: GameState,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(from = "StoredGame<S>")]
 pub struct Game<S> {
     pub state: S,
+    pub rules: Ruleset,
+    /// Seat that dealt the current hand. The deal passes to the left after every hand.
+    pub dealer: usize,
+    /// Position on the dealer's left, who opens the bidding and leads unless a contract says
+    /// otherwise. Like `turn` and every player index inside the hand states, this is a hand
+    /// position; `rules` maps positions to table seats.
     pub first: usize,
+    /// Number of the current hand, starting at 1.
+    pub hand: usize,
     pub turn: usize,
     pub cards: CardsInPlay,
-    pub score: [PlayerScore; 3],
+    /// Scores by table seat.
+    pub score: Vec<PlayerScore>,
     pub refas: Refas,
+    pub ledger: ScoreLedger,
+    /// Cards played this hand under honor mode, each marked legal or not. Kept out of the
+    /// table's view, which would otherwise give every revoke away as it happens.
+    pub plays: Vec<Play>,
+}
+
+/// `Game` as it's read back, which also accepts games saved before the dealer was kept.
+#[derive(Deserialize)]
+struct StoredGame<S> {
+    state: S,
+    #[serde(default)]
+    rules: Ruleset,
+    dealer: Option<usize>,
+    first: usize,
+    #[serde(default)]
+    hand: usize,
+    turn: usize,
+    cards: CardsInPlay,
+    #[serde(deserialize_with = "deserialize_score")]
+    score: Vec<PlayerScore>,
+    refas: Refas,
+    #[serde(default)]
+    ledger: ScoreLedger,
+    #[serde(default)]
+    plays: Vec<Play>,
+}
+
+impl<S> From<StoredGame<S>> for Game<S> {
+    fn from(game: StoredGame<S>) -> Self {
+        // Those games had no sit-out, so `first` was the seat on the dealer's left.
+        let dealer = game
+            .dealer
+            .unwrap_or_else(|| game.rules.dealer_before(game.first));
+        Game {
+            state: game.state,
+            rules: game.rules,
+            dealer,
+            first: game.first,
+            hand: game.hand,
+            turn: game.turn,
+            cards: game.cards,
+            score: game.score,
+            refas: game.refas,
+            ledger: game.ledger,
+            plays: game.plays,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -50,8 +107,18 @@ impl Refas {
         self.left > 0
     }
 
-    pub fn add_active_refa(&mut self) {
-        self.active.push_back(Refa::default());
+    /// Writes a refa for every seat, out of the ones left.
+    pub fn add_active_refa(&mut self, seats: usize) {
+        debug_assert!(self.has_refas_left(), "no refa left to write");
+        self.left -= 1;
+        self.active.push_back(Refa {
+            used_by: vec![false; seats],
+        });
+    }
+
+    /// Some refa is still being played.
+    pub fn is_any_active(&self) -> bool {
+        !self.active.is_empty()
     }
 
     pub fn has_active_refa(&self, player: usize) -> bool {
@@ -76,7 +143,8 @@ impl Refas {
 
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct Refa {
-    used_by: [bool; 3],
+    /// Indexed by table seat.
+    used_by: Vec<bool>,
 }
 
 impl Refa {
@@ -90,9 +158,9 @@ impl Refa {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
 pub struct CardsInPlay {
-    pub hands: [Vec<Card>; 3],
+    pub hands: [CardSet; 3],
     pub hidden: [Card; 2],
 }
 
@@ -106,30 +174,7 @@ impl CardsInPlay {
 
     /// Generate a full 32-card deck: all suits and values
     fn create_deck() -> Vec<Card> {
-        let suits = [
-            CardSuit::Spades,
-            CardSuit::Diamonds,
-            CardSuit::Hearts,
-            CardSuit::Clubs,
-        ];
-        let values = [
-            CardValue::Seven,
-            CardValue::Eight,
-            CardValue::Nine,
-            CardValue::Ten,
-            CardValue::Jack,
-            CardValue::Queen,
-            CardValue::King,
-            CardValue::Ace,
-        ];
-
-        let mut deck = Vec::with_capacity(suits.len() * values.len());
-        for &suit in &suits {
-            for &value in &values {
-                deck.push(Card { suit, value });
-            }
-        }
-        deck
+        CardSet::DECK.iter().collect()
     }
 
     /// Shuffle the given deck in place
@@ -139,7 +184,7 @@ impl CardsInPlay {
     }
 
     /// Deal n cards for each of `players` hands from the deck
-    fn deal_hands(deck: &mut Vec<Card>, players: usize, cards_per_hand: usize) -> Vec<Vec<Card>> {
+    fn deal_hands(deck: &mut Vec<Card>, players: usize, cards_per_hand: usize) -> Vec<CardSet> {
         (0..players)
             .map(|_| deck.drain(0..cards_per_hand).collect())
             .collect()
@@ -162,28 +207,72 @@ impl CardsInPlay {
         let hidden_vec = Self::deal_hidden(&mut deck, 2);
 
         // 4) Convert into fixed-size arrays
-        let [hand1, hand2, hand3]: [Vec<Card>; 3] = hands_vec.try_into().unwrap();
-        let hands = [hand1, hand2, hand3];
+        let hands: [CardSet; 3] = hands_vec.try_into().unwrap();
         let hidden = [hidden_vec[0], hidden_vec[1]];
 
         CardsInPlay { hands, hidden }
     }
 }
 
-#[derive(Debug, Default, Clone, Copy, Deserialize, Serialize)]
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
 pub struct PlayerScore {
-    bulls: u32,
-    soups: [u32; 2],
+    /// Bula can go below zero once a player has passed more than they started with.
+    bulls: i32,
+    /// Soups this player wrote on each seat, indexed by the target seat. The own seat stays zero.
+    soups: Vec<u32>,
+}
+
+/// Reads scores stored before soups were kept per target seat. Each player then had a column
+/// for each other seat, lowest first, so giving them one for their own seat lines them up.
+fn deserialize_score<'de, D>(deserializer: D) -> Result<Vec<PlayerScore>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let mut score = Vec::<PlayerScore>::deserialize(deserializer)?;
+    let seats = score.len();
+    for (seat, player) in score.iter_mut().enumerate() {
+        if player.soups.len() + 1 == seats {
+            player.soups.insert(seat, 0);
+        }
+    }
+
+    Ok(score)
 }
 
 impl PlayerScore {
-    pub fn new(bulls: u32) -> Self {
+    pub fn new(bulls: u32, seats: usize) -> Self {
         Self {
-            bulls,
-            soups: [0; 2],
+            bulls: bulls as i32,
+            soups: vec![0; seats],
         }
     }
 
+    pub fn bulls(&self) -> i32 {
+        self.bulls
+    }
+
+    /// Soups this player wrote on `target`.
+    pub fn soups_on(&self, target: usize) -> u32 {
+        self.soups[target]
+    }
+
+    pub fn total_soups(&self) -> u32 {
+        self.soups.iter().sum()
+    }
+
+    /// Makes the changes since `before` count twice.
+    pub fn double_changes(&mut self, before: &PlayerScore) {
+        self.bulls += self.bulls - before.bulls;
+        for (soups, before) in self.soups.iter_mut().zip(&before.soups) {
+            *soups += *soups - before;
+        }
+    }
+
+    /// Adds bula outside of a contract, like the tricks taken in an all-pass play-out.
+    pub fn add_bulls(&mut self, bulls: u32) {
+        self.bulls += bulls as i32;
+    }
+
     pub fn apply_result(
         &mut self,
         contract: GameContractData,
@@ -191,9 +280,9 @@ impl PlayerScore {
         contre: ContreLevel,
     ) {
         if is_passed {
-            self.bulls -= Self::contract_value(contract, contre);
+            self.bulls -= Self::contract_value(contract, contre) as i32;
         } else {
-            self.bulls += Self::contract_value(contract, contre);
+            self.bulls += Self::contract_value(contract, contre) as i32;
         }
     }
 
@@ -201,10 +290,10 @@ impl PlayerScore {
         &mut self,
         contract: GameContractData,
         num_soups: u32,
-        soups_ind: usize,
+        target: usize,
         contre: ContreLevel,
     ) {
-        self.soups[soups_ind] += num_soups * Self::contract_value(contract, contre);
+        self.soups[target] += num_soups * Self::contract_value(contract, contre);
     }
 
     fn contract_value(contract: GameContractData, contre: ContreLevel) -> u32 {
@@ -226,6 +315,16 @@ impl PlayerScore {
 }
 
 impl<StateType> Game<StateType> {
+    /// Table seat playing a hand position this hand.
+    pub fn seat_of(&self, position: usize) -> usize {
+        self.rules.seat_of(self.dealer, position)
+    }
+
+    /// Hand position of a table seat this hand, `None` for a dealer sitting out.
+    pub fn position_of(&self, seat: usize) -> Option<usize> {
+        self.rules.position_of(self.dealer, seat)
+    }
+
     pub fn validate_turn(&self, action: &GameAction) -> Result<(), GameError> {
         if self.is_player_turn(action.player) {
             Ok(())
@@ -237,6 +336,20 @@ impl<StateType> Game<StateType> {
     fn is_player_turn(&self, player: usize) -> bool {
         return self.turn == player;
     }
+
+    /// Called once a hand `declarer` played is scored. A declarer with a refa open plays the
+    /// hand for double, which uses their refa up. Returns whether it did.
+    pub(super) fn play_refa(&mut self, declarer: usize, score_before: &[PlayerScore]) -> bool {
+        if !self.refas.has_active_refa(declarer) {
+            return false;
+        }
+
+        for (score, before) in self.score.iter_mut().zip(score_before) {
+            score.double_changes(before);
+        }
+        self.refas.mark_active_refa(declarer);
+        true
+    }
 }
 
 pub fn turn_inc(turn: usize) -> usize {
@@ -258,20 +371,78 @@ pub enum GameState {
     HelpOrContreToContract(Game<HelpOrContreToContractState>),
     ContreDeclared(Game<ContreDeclaredState>),
     Playing(Game<PlayingState>),
+    AllPass(Game<AllPassState>),
+}
+
+/// Evaluates `$body` with `$game` bound to the inner `Game<S>`, whatever the state.
+macro_rules! with_game {
+    ($state:expr, $game:ident => $body:expr) => {
+        match $state {
+            GameState::Bidding($game) => $body,
+            GameState::NoBidPlayClaim($game) => $body,
+            GameState::NoBidPlayChoice($game) => $body,
+            GameState::ChoosingCards($game) => $body,
+            GameState::ChoosingContract($game) => $body,
+            GameState::RespondingToContract($game) => $body,
+            GameState::HelpOrContreToContract($game) => $body,
+            GameState::ContreDeclared($game) => $body,
+            GameState::Playing($game) => $body,
+            GameState::AllPass($game) => $body,
+        }
+    };
 }
 
 impl GameState {
     pub fn apply(self, action: GameAction) -> Result<GameState, GameError> {
+        with_game!(self, game => game.apply(action))
+    }
+
+    pub fn cards(&self) -> &CardsInPlay {
+        with_game!(self, game => &game.cards)
+    }
+
+    pub fn score(&self) -> &[PlayerScore] {
+        with_game!(self, game => &game.score)
+    }
+
+    pub fn rules(&self) -> &Ruleset {
+        with_game!(self, game => &game.rules)
+    }
+
+    pub fn dealer(&self) -> usize {
+        with_game!(self, game => game.dealer)
+    }
+
+    pub fn position_of(&self, seat: usize) -> Option<usize> {
+        with_game!(self, game => game.position_of(seat))
+    }
+
+    pub fn ledger(&self) -> &ScoreLedger {
+        with_game!(self, game => &game.ledger)
+    }
+
+    /// Hands lying face up for everyone at the table, by hand position.
+    pub fn exposed_hands(&self) -> Vec<(usize, CardSet)> {
         match self {
-            GameState::Bidding(game) => game.apply(action),
-            GameState::NoBidPlayClaim(game) => game.apply(action),
-            GameState::NoBidPlayChoice(game) => game.apply(action),
-            GameState::ChoosingCards(game) => game.apply(action),
-            GameState::ChoosingContract(game) => game.apply(action),
-            GameState::RespondingToContract(game) => game.apply(action),
-            GameState::HelpOrContreToContract(game) => game.apply(action),
-            GameState::ContreDeclared(game) => game.apply(action),
-            GameState::Playing(game) => game.apply(action),
+            GameState::Playing(game) => game.exposed_hands().collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// True once a hand has been scored that brought every bula down to zero with no refa left
+    /// to play, which closes the game.
+    pub fn is_game_over(&self) -> bool {
+        self.is_hand_start()
+            && with_game!(self, game => {
+                game.score.iter().all(|score| score.bulls() <= 0) && !game.refas.is_any_active()
+            })
+    }
+
+    /// True when a hand has just been dealt and nobody has acted on it yet.
+    pub fn is_hand_start(&self) -> bool {
+        match self {
+            GameState::Bidding(game) => game.is_hand_start(),
+            _ => false,
         }
     }
 }
@@ -293,36 +464,176 @@ pub fn get_third(ind1: usize, ind2: usize) -> usize {
     return 3 - ind1 - ind2;
 }
 
-pub fn new_game(first: usize, starting_score: u32, num_refas: usize) -> GameState {
-    GameState::Bidding(<Game<BiddingState>>::new(first, starting_score, num_refas))
+pub fn new_game(first: usize, starting_score: u32, num_refas: usize, rules: Ruleset) -> GameState {
+    GameState::Bidding(<Game<BiddingState>>::new(
+        first,
+        starting_score,
+        num_refas,
+        rules,
+    ))
 }
 
-impl<'r> Decode<'r, Postgres> for GameState {
-    fn decode(
-        value: PgValueRef<'r>,
-    ) -> Result<Self, Box<dyn std::error::Error + 'static + Send + Sync>> {
-        let json: serde_json::Value = Decode::<'r, Postgres>::decode(value)?;
-        Ok(serde_json::from_value(json)?)
-    }
+/// Stores a serde type in a JSONB column.
+macro_rules! pg_json {
+    ($type:ty) => {
+        impl<'r> sqlx::Decode<'r, sqlx::Postgres> for $type {
+            fn decode(
+                value: sqlx::postgres::PgValueRef<'r>,
+            ) -> Result<Self, Box<dyn std::error::Error + 'static + Send + Sync>> {
+                let json: serde_json::Value = sqlx::Decode::<'r, sqlx::Postgres>::decode(value)?;
+                Ok(serde_json::from_value(json)?)
+            }
+        }
+
+        impl<'q> sqlx::Encode<'q, sqlx::Postgres> for $type {
+            fn encode_by_ref(
+                &self,
+                buf: &mut sqlx::postgres::PgArgumentBuffer,
+            ) -> Result<sqlx::encode::IsNull, Box<dyn std::error::Error + 'static + Send + Sync>>
+            {
+                let json = serde_json::to_value(self).unwrap();
+                json.encode_by_ref(buf)
+            }
+
+            fn size_hint(&self) -> usize {
+                let json = serde_json::to_value(self).unwrap();
+                json.size_hint()
+            }
+        }
+
+        impl sqlx::Type<sqlx::Postgres> for $type {
+            fn type_info() -> sqlx::postgres::PgTypeInfo {
+                <serde_json::Value as sqlx::Type<sqlx::Postgres>>::type_info()
+            }
+        }
+    };
 }
 
-impl<'q> Encode<'q, Postgres> for GameState {
-    fn encode_by_ref(
-        &self,
-        buf: &mut sqlx::postgres::PgArgumentBuffer,
-    ) -> Result<sqlx::encode::IsNull, Box<dyn std::error::Error + 'static + Send + Sync>> {
-        let json = serde_json::to_value(self).unwrap();
-        json.encode_by_ref(buf)
+pub(crate) use pg_json;
+
+pg_json!(GameState);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{
+        actions::{CardChoice, GameActionKind},
+        types::GameContract,
+    };
+
+    /// A hand in the bidding, saved before soups were kept per target seat: the declarer of
+    /// the previous hand sat in seat 1.
+    const LEGACY_STATE: &str = r#"
+{"Bidding":{"state":{"bid":null,"can_steal_bid":false,"player_states":["NoBid","NoBid","NoBid"]},"first":2,"turn":2,"cards":{"hands":[[{"suit":"Diamonds","value":"Jack"},{"suit":"Spades","value":"Queen"},{"suit":"Diamonds","value":"King"},{"suit":"Spades","value":"Jack"},{"suit":"Hearts","value":"Nine"},{"suit":"Hearts","value":"Jack"},{"suit":"Hearts","value":"Eight"},{"suit":"Clubs","value":"King"},{"suit":"Spades","value":"Ten"},{"suit":"Hearts","value":"Seven"}],[{"suit":"Clubs","value":"Ace"},{"suit":"Diamonds","value":"Ten"},{"suit":"Hearts","value":"Queen"},{"suit":"Spades","value":"Eight"},{"suit":"Clubs","value":"Nine"},{"suit":"Spades","value":"Seven"},{"suit":"Clubs","value":"Ten"},{"suit":"Diamonds","value":"Queen"},{"suit":"Diamonds","value":"Eight"},{"suit":"Clubs","value":"Eight"}],[{"suit":"Diamonds","value":"Ace"},{"suit":"Hearts","value":"Ten"},{"suit":"Clubs","value":"Queen"},{"suit":"Spades","value":"Nine"},{"suit":"Clubs","value":"Jack"},{"suit":"Clubs","value":"Seven"},{"suit":"Diamonds","value":"Nine"},{"suit":"Hearts","value":"Ace"},{"suit":"Diamonds","value":"Seven"},{"suit":"Hearts","value":"King"}]],"hidden":[{"suit":"Spades","value":"Ace"},{"suit":"Spades","value":"King"}]},"score":[{"bulls":60,"soups":[12,0]},{"bulls":66,"soups":[0,0]},{"bulls":60,"soups":[0,18]}],"refas":{"active":[],"left":2}}}"#;
+
+    #[test]
+    fn legacy_scores_load_by_seat() {
+        let state: GameState = serde_json::from_str(LEGACY_STATE).unwrap();
+        let score = state.score();
+
+        assert_eq!(score[0].soups_on(1), 12);
+        assert_eq!(score[2].soups_on(1), 18);
+        assert_eq!(score[1].total_soups(), 0);
+        assert!(score.iter().all(|player| player.soups.len() == 3));
+        assert_eq!(score[1].bulls(), 66);
     }
 
-    fn size_hint(&self) -> usize {
-        let json = serde_json::to_value(self).unwrap();
-        json.size_hint()
+    #[test]
+    fn legacy_dealer_sits_before_first() {
+        let GameState::Bidding(game) = serde_json::from_str(LEGACY_STATE).unwrap() else {
+            panic!("The fixture is in the bidding");
+        };
+
+        assert_eq!(game.first, 2);
+        assert_eq!(game.dealer, 1);
+    }
+
+    #[test]
+    fn the_deal_passes_to_the_left_after_every_hand() {
+        let mut state = new_game(0, 30, 0, Ruleset::default());
+        for (dealer, first) in [(2, 0), (0, 1), (1, 2), (2, 0)] {
+            let GameState::Bidding(game) = &state else {
+                panic!("Every hand is passed out and dealt again");
+            };
+            assert_eq!((game.dealer, game.first, game.turn), (dealer, first, first));
+
+            for _ in 0..3 {
+                state = act(state, GameActionKind::PassBid);
+            }
+        }
     }
-}
 
-impl Type<Postgres> for GameState {
-    fn type_info() -> PgTypeInfo {
-        <serde_json::Value as Type<Postgres>>::type_info()
+    /// Has whoever's turn it is take `kind`.
+    fn act(state: GameState, kind: GameActionKind) -> GameState {
+        let turn = match &state {
+            GameState::Bidding(game) => game.turn,
+            GameState::ChoosingCards(game) => game.turn,
+            GameState::ChoosingContract(game) => game.turn,
+            GameState::RespondingToContract(game) => game.turn,
+            GameState::HelpOrContreToContract(game) => game.turn,
+            _ => panic!("Not a state these tests reach"),
+        };
+        state.apply(GameAction::new(turn, kind)).unwrap()
+    }
+
+    #[test]
+    fn a_passed_out_hand_doubles_the_next_one() {
+        let mut state = new_game(0, 30, 2, Ruleset::default());
+        for _ in 0..3 {
+            state = act(state, GameActionKind::PassBid);
+        }
+
+        let GameState::Bidding(mut game) = state else {
+            panic!("A hand everyone passes is dealt again");
+        };
+        assert_eq!(game.hand, 2);
+        assert_eq!(game.refas.left, 1);
+        assert!((0..3).all(|seat| game.refas.has_active_refa(seat)));
+
+        // Seat 1 opens the second hand and declares diamonds, seat 2 passes and seat 0
+        // defends alone, taking the two tricks the declarer leaves them
+        game.cards = "♠987 ♦QJ10 ♥KQJ ♣Q / ♠AKQJ10 ♦AK ♥A ♣AK / ♠- ♦987 ♥1098 ♣J1098 / 7♥ 7♣"
+            .parse()
+            .unwrap();
+        let card = |s: &str| s.parse().unwrap();
+        let mut state = GameState::Bidding(game);
+        for kind in [
+            GameActionKind::Bid,
+            GameActionKind::PassBid,
+            GameActionKind::PassBid,
+            GameActionKind::ChooseCards(CardChoice {
+                take_cards: vec![card("7♥"), card("7♣")],
+                discard_cards: vec![card("A♦"), card("K♦")],
+            }),
+            GameActionKind::ChooseContract(GameContract::Diamonds),
+            GameActionKind::RejectContract,
+            GameActionKind::AcceptContract,
+        ] {
+            state = act(state, kind);
+        }
+        while let GameState::HelpOrContreToContract(_) = state {
+            state = act(state, GameActionKind::PassHelpContre);
+        }
+        for (player, kind) in [
+            (1, GameActionKind::Claim { tricks: 8 }),
+            (0, GameActionKind::AcceptClaim),
+            (2, GameActionKind::AcceptClaim),
+        ] {
+            state = state.apply(GameAction::new(player, kind)).unwrap();
+        }
+
+        let GameState::Bidding(game) = state else {
+            panic!("The claim ends the hand");
+        };
+        let entry = game.ledger.entries().last().unwrap();
+        assert!(entry.refa);
+        // Twice what diamonds are worth without a refa: 6 bula and 6 soups a trick
+        assert_eq!(entry.bula[1], -12);
+        assert_eq!(game.score[1].bulls(), 18);
+        assert_eq!(game.score[0].soups_on(1), 24);
+        // Only the declarer has played their refa
+        assert!(!game.refas.has_active_refa(1));
+        assert!(game.refas.has_active_refa(0));
+        assert!(game.refas.has_active_refa(2));
     }
 }
@@ -0,0 +1,275 @@
+use std::fmt::{self, Write};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    choosing::ContreLevel,
+    game::PlayerScore,
+    settlement::Settlement,
+    types::{GameContractData, GameContractKind},
+};
+
+/// Everything that was written on the score sheet for one scored hand.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LedgerEntry {
+    pub hand: usize,
+    /// Table seat of the declarer, like every player index in the ledger. Hands everyone
+    /// passed and played out have no declarer and no contract.
+    pub declarer: Option<usize>,
+    pub contract: Option<GameContractData>,
+    pub contre_level: ContreLevel,
+    pub refa: bool,
+    /// Seat whose revoke was called in honor mode, losing the hand for their side.
+    #[serde(default)]
+    pub revoke: Option<usize>,
+    /// Bula change of each player, negative when the bula went down.
+    pub bula: Vec<i64>,
+    /// `soups[writer][target]`: soups `writer` wrote on `target` this hand.
+    pub soups: Vec<Vec<u32>>,
+}
+
+impl LedgerEntry {
+    pub fn new(
+        hand: usize,
+        declarer: usize,
+        contract: GameContractData,
+        contre_level: ContreLevel,
+        refa: bool,
+    ) -> Self {
+        Self {
+            hand,
+            declarer: Some(declarer),
+            contract: Some(contract),
+            contre_level,
+            refa,
+            revoke: None,
+            bula: Vec::new(),
+            soups: Vec::new(),
+        }
+    }
+
+    /// Entry for a hand everyone passed and played out.
+    pub fn all_pass(hand: usize) -> Self {
+        Self {
+            hand,
+            declarer: None,
+            contract: None,
+            contre_level: ContreLevel::NoContre,
+            refa: false,
+            revoke: None,
+            bula: Vec::new(),
+            soups: Vec::new(),
+        }
+    }
+
+    /// Fills in the changes between the scores before and after the hand was scored.
+    pub fn with_changes(mut self, before: &[PlayerScore], after: &[PlayerScore]) -> Self {
+        let seats = after.len();
+        self.bula = (0..seats)
+            .map(|player| (after[player].bulls() - before[player].bulls()) as i64)
+            .collect();
+        self.soups = (0..seats)
+            .map(|player| {
+                (0..seats)
+                    .map(|target| after[player].soups_on(target) - before[player].soups_on(target))
+                    .collect()
+            })
+            .collect();
+
+        self
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct ScoreLedger {
+    entries: Vec<LedgerEntry>,
+}
+
+impl ScoreLedger {
+    pub fn push(&mut self, entry: LedgerEntry) {
+        self.entries.push(entry);
+    }
+
+    pub fn entries(&self) -> &[LedgerEntry] {
+        &self.entries
+    }
+}
+
+/// Renders the ledger as the traditional score sheet, one column per player holding the soups
+/// written on the left neighbour, the bula and the soups written on the right neighbour. At a
+/// table of four the soups on the player across sit between the left neighbour and the bula.
+/// Only values that changed are written on a row, like on paper, and the settled result of
+/// each player closes the column.
+pub struct ScoreSheet<'a> {
+    pub ledger: &'a ScoreLedger,
+    pub score: &'a [PlayerScore],
+    pub players: &'a [String],
+}
+
+const CELL: usize = 6;
+
+/// What one cell of a player's column holds.
+#[derive(Clone, Copy)]
+enum Cell {
+    Soups(usize),
+    Bula,
+}
+
+impl ScoreSheet<'_> {
+    fn seats(&self) -> usize {
+        self.score.len()
+    }
+
+    fn column_width(&self) -> usize {
+        CELL * self.seats() + 1
+    }
+
+    /// The other seats going right to left from the left neighbour, with the bula before the
+    /// right neighbour.
+    fn cells(&self, player: usize) -> Vec<Cell> {
+        let seats = self.seats();
+        let mut cells: Vec<Cell> = (1..seats)
+            .map(|offset| Cell::Soups((player + seats - offset) % seats))
+            .collect();
+        cells.insert(seats - 2, Cell::Bula);
+        cells
+    }
+
+    fn starting_bula(&self) -> Vec<i64> {
+        (0..self.seats())
+            .map(|player| {
+                let changes: i64 = self.ledger.entries.iter().map(|e| e.bula[player]).sum();
+                self.score[player].bulls() as i64 - changes
+            })
+            .collect()
+    }
+
+    fn player_name(&self, player: usize) -> String {
+        self.players
+            .get(player)
+            .filter(|name| !name.is_empty())
+            .cloned()
+            .unwrap_or_else(|| format!("Player {}", player + 1))
+    }
+
+    fn write_row(f: &mut fmt::Formatter<'_>, label: &str, cells: &[Vec<String>]) -> fmt::Result {
+        write!(f, "{:>4} ", label)?;
+        for column in cells {
+            f.write_str("| ")?;
+            for cell in column {
+                write!(f, "{:>CELL$}", cell)?;
+            }
+            f.write_str(" ")?;
+        }
+        writeln!(f)
+    }
+
+    fn write_banner(&self, f: &mut fmt::Formatter<'_>, values: &[String]) -> fmt::Result {
+        let width = self.column_width();
+        write!(f, "{:>4} ", "")?;
+        for value in values {
+            write!(f, "| {:^width$}", value)?;
+        }
+        writeln!(f)
+    }
+}
+
+impl fmt::Display for ScoreSheet<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let seats = self.seats();
+        let names: Vec<String> = (0..seats).map(|p| self.player_name(p)).collect();
+        self.write_banner(f, &names)?;
+
+        let headers: Vec<Vec<String>> = (0..seats)
+            .map(|player| {
+                self.cells(player)
+                    .into_iter()
+                    .map(|cell| match cell {
+                        Cell::Soups(target) => format!(">{}", target + 1),
+                        Cell::Bula => "bula".into(),
+                    })
+                    .collect()
+            })
+            .collect();
+        Self::write_row(f, "", &headers)?;
+
+        let mut bula = self.starting_bula();
+        let mut soups = vec![vec![0u32; seats]; seats];
+
+        let first_row: Vec<Vec<String>> = (0..seats)
+            .map(|player| {
+                self.cells(player)
+                    .into_iter()
+                    .map(|cell| match cell {
+                        Cell::Soups(_) => String::new(),
+                        Cell::Bula => bula[player].to_string(),
+                    })
+                    .collect()
+            })
+            .collect();
+        Self::write_row(f, "", &first_row)?;
+
+        for entry in &self.ledger.entries {
+            let mut cells = Vec::with_capacity(seats);
+
+            for player in 0..seats {
+                let column = self
+                    .cells(player)
+                    .into_iter()
+                    .map(|cell| match cell {
+                        Cell::Soups(target) if entry.soups[player][target] > 0 => {
+                            soups[player][target] += entry.soups[player][target];
+                            soups[player][target].to_string()
+                        }
+                        Cell::Bula if entry.bula[player] != 0 => {
+                            bula[player] += entry.bula[player];
+                            bula[player].to_string()
+                        }
+                        _ => String::new(),
+                    })
+                    .collect();
+                cells.push(column);
+            }
+
+            Self::write_row(f, &entry.hand.to_string(), &cells)?;
+        }
+
+        let settlement = Settlement::new(self.score);
+        let results: Vec<String> = settlement
+            .results
+            .iter()
+            .map(|result| format!("{:+}", result))
+            .collect();
+        self.write_banner(f, &results)?;
+
+        writeln!(f)?;
+        for entry in &self.ledger.entries {
+            let (Some(declarer), Some(contract)) = (entry.declarer, entry.contract) else {
+                writeln!(f, "{:>4}  All pass", entry.hand)?;
+                continue;
+            };
+
+            let mut line = format!(
+                "{:>4}  {} {}",
+                entry.hand,
+                self.player_name(declarer),
+                contract.value
+            );
+            if let GameContractKind::NoBid = contract.kind {
+                line.push_str(" without talon");
+            }
+            if entry.contre_level != ContreLevel::NoContre {
+                write!(line, ", {:?}", entry.contre_level)?;
+            }
+            if entry.refa {
+                line.push_str(", refa");
+            }
+            if let Some(revoke) = entry.revoke {
+                write!(line, ", revoke by {}", self.player_name(revoke))?;
+            }
+            writeln!(f, "{}", line)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,490 @@
+use std::{fmt, str::FromStr};
+
+use super::{
+    card_set::CardSet,
+    game::CardsInPlay,
+    types::{Card, CardSuit, CardValue, GameContract},
+};
+
+/*
+   Compact text notation for cards, hands and deals.
+
+   Card:  value followed by suit, e.g. `A♠`, `10♥`, `7♣`. Suits can also be written with
+          their letter (`S`, `D`, `H`, `C`, case insensitive), so `10h` and `KD` parse too.
+   Hand:  one group per suit, highest card first, `-` for a void, e.g. `♠AK10 ♦QJ7 ♥- ♣987`.
+   Deal:  three hands and the talon separated by `/`, e.g. `<hand> / <hand> / <hand> / 7♠ 8♦`.
+
+   The alternate flag (`{:#}`) writes suits as letters for places that can't handle unicode.
+*/
+
+const HAND_SIZE: usize = 10;
+const TALON_SIZE: usize = 2;
+
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum NotationError {
+    #[error("invalid suit `{0}`")]
+    InvalidSuit(String),
+
+    #[error("invalid card value `{0}`")]
+    InvalidValue(String),
+
+    #[error("invalid card `{0}`")]
+    InvalidCard(String),
+
+    #[error("invalid contract `{0}`")]
+    InvalidContract(String),
+
+    #[error("invalid hand group `{0}`")]
+    InvalidHandGroup(String),
+
+    #[error("card {0} appears more than once")]
+    DuplicateCard(Card),
+
+    #[error("deal should have 3 hands and a talon, found {0} parts")]
+    DealParts(usize),
+
+    #[error("expected {expected} cards, found {found}")]
+    CardCount { expected: usize, found: usize },
+}
+
+impl CardSuit {
+    fn symbol(&self) -> char {
+        match self {
+            CardSuit::Spades => '♠',
+            CardSuit::Diamonds => '♦',
+            CardSuit::Hearts => '♥',
+            CardSuit::Clubs => '♣',
+        }
+    }
+
+    fn letter(&self) -> char {
+        match self {
+            CardSuit::Spades => 'S',
+            CardSuit::Diamonds => 'D',
+            CardSuit::Hearts => 'H',
+            CardSuit::Clubs => 'C',
+        }
+    }
+
+    fn from_char(c: char) -> Option<Self> {
+        match c.to_ascii_uppercase() {
+            '♠' | 'S' => Some(CardSuit::Spades),
+            '♦' | 'D' => Some(CardSuit::Diamonds),
+            '♥' | 'H' => Some(CardSuit::Hearts),
+            '♣' | 'C' => Some(CardSuit::Clubs),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CardSuit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            write!(f, "{}", self.letter())
+        } else {
+            write!(f, "{}", self.symbol())
+        }
+    }
+}
+
+impl FromStr for CardSuit {
+    type Err = NotationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => CardSuit::from_char(c),
+            _ => None,
+        }
+        .ok_or_else(|| NotationError::InvalidSuit(s.into()))
+    }
+}
+
+impl CardValue {
+    fn notation(&self) -> &'static str {
+        match self {
+            CardValue::Seven => "7",
+            CardValue::Eight => "8",
+            CardValue::Nine => "9",
+            CardValue::Ten => "10",
+            CardValue::Jack => "J",
+            CardValue::Queen => "Q",
+            CardValue::King => "K",
+            CardValue::Ace => "A",
+        }
+    }
+}
+
+impl fmt::Display for CardValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.notation())
+    }
+}
+
+impl FromStr for CardValue {
+    type Err = NotationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "7" => Ok(CardValue::Seven),
+            "8" => Ok(CardValue::Eight),
+            "9" => Ok(CardValue::Nine),
+            "10" | "T" => Ok(CardValue::Ten),
+            "J" => Ok(CardValue::Jack),
+            "Q" => Ok(CardValue::Queen),
+            "K" => Ok(CardValue::King),
+            "A" => Ok(CardValue::Ace),
+            _ => Err(NotationError::InvalidValue(s.into())),
+        }
+    }
+}
+
+/// Splits a run of values like `AK107` into individual values.
+fn parse_values(s: &str) -> Result<Vec<CardValue>, NotationError> {
+    let mut values = Vec::new();
+    let mut rest = s;
+
+    while !rest.is_empty() {
+        let len = if rest.starts_with("10") { 2 } else { 1 };
+        let Some(token) = rest.get(..len) else {
+            return Err(NotationError::InvalidValue(rest.into()));
+        };
+
+        values.push(token.parse()?);
+        rest = &rest[len..];
+    }
+
+    Ok(values)
+}
+
+impl fmt::Display for Card {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.value)?;
+        self.suit.fmt(f)
+    }
+}
+
+impl FromStr for Card {
+    type Err = NotationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || NotationError::InvalidCard(s.into());
+
+        let (split, suit_char) = s.char_indices().last().ok_or_else(invalid)?;
+        let suit = CardSuit::from_char(suit_char).ok_or_else(invalid)?;
+        let value = s[..split].parse().map_err(|_| invalid())?;
+
+        Ok(Card { suit, value })
+    }
+}
+
+/// Displays a hand grouped by suit, e.g. `♠AK10 ♦QJ7 ♥- ♣987`.
+impl fmt::Display for CardSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, suit) in CardSuit::ALL.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            suit.fmt(f)?;
+
+            let in_suit = self.in_suit(*suit);
+            if in_suit.is_empty() {
+                f.write_str("-")?;
+            }
+
+            for card in in_suit.iter().rev() {
+                write!(f, "{}", card.value)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses a hand written in suit groups. Suits may be omitted or given in any order.
+impl FromStr for CardSet {
+    type Err = NotationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut cards = CardSet::EMPTY;
+
+        for group in s.split_whitespace() {
+            let mut chars = group.chars();
+            let suit = chars
+                .next()
+                .and_then(CardSuit::from_char)
+                .ok_or_else(|| NotationError::InvalidHandGroup(group.into()))?;
+
+            let values = chars.as_str();
+            if values == "-" {
+                continue;
+            }
+
+            for value in parse_values(values)? {
+                let card = Card { suit, value };
+                if !cards.insert(card) {
+                    return Err(NotationError::DuplicateCard(card));
+                }
+            }
+        }
+
+        Ok(cards)
+    }
+}
+
+/// Parses whitespace separated cards, e.g. `7♠ 8♦`.
+pub fn parse_cards(s: &str) -> Result<Vec<Card>, NotationError> {
+    let mut cards = Vec::new();
+    for token in s.split_whitespace() {
+        push_unique(&mut cards, token.parse()?)?;
+    }
+
+    Ok(cards)
+}
+
+/// Displays cards separated by spaces, in the order given.
+pub struct CardsNotation<'a>(pub &'a [Card]);
+
+impl fmt::Display for CardsNotation<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, card) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            card.fmt(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn push_unique(cards: &mut Vec<Card>, card: Card) -> Result<(), NotationError> {
+    if cards.contains(&card) {
+        return Err(NotationError::DuplicateCard(card));
+    }
+
+    cards.push(card);
+    Ok(())
+}
+
+fn expect_count(found: usize, expected: usize) -> Result<(), NotationError> {
+    if found == expected {
+        Ok(())
+    } else {
+        Err(NotationError::CardCount { expected, found })
+    }
+}
+
+impl fmt::Display for CardsInPlay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for hand in &self.hands {
+            hand.fmt(f)?;
+            f.write_str(" / ")?;
+        }
+
+        CardsNotation(&self.hidden).fmt(f)
+    }
+}
+
+impl FromStr for CardsInPlay {
+    type Err = NotationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split('/').collect();
+        let [hand1, hand2, hand3, talon] = parts[..] else {
+            return Err(NotationError::DealParts(parts.len()));
+        };
+
+        let mut seen = CardSet::EMPTY;
+        let mut hands = [CardSet::EMPTY; 3];
+        for (hand, part) in hands.iter_mut().zip([hand1, hand2, hand3]) {
+            *hand = part.parse()?;
+            expect_count(hand.len(), HAND_SIZE)?;
+            if let Some(card) = (seen & *hand).lowest() {
+                return Err(NotationError::DuplicateCard(card));
+            }
+            seen = seen | *hand;
+        }
+
+        let talon = parse_cards(talon)?;
+        expect_count(talon.len(), TALON_SIZE)?;
+        if let Some(card) = talon.iter().find(|&&card| seen.contains(card)) {
+            return Err(NotationError::DuplicateCard(*card));
+        }
+
+        Ok(CardsInPlay {
+            hands,
+            hidden: [talon[0], talon[1]],
+        })
+    }
+}
+
+impl fmt::Display for GameContract {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            GameContract::Spades => "Spades",
+            GameContract::Diamonds => "Diamonds",
+            GameContract::Hearts => "Hearts",
+            GameContract::Clubs => "Clubs",
+            GameContract::Betl => "Betl",
+            GameContract::Sans => "Sans",
+        };
+
+        f.write_str(name)
+    }
+}
+
+impl FromStr for GameContract {
+    type Err = NotationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "spades" => Ok(GameContract::Spades),
+            "diamonds" => Ok(GameContract::Diamonds),
+            "hearts" => Ok(GameContract::Hearts),
+            "clubs" => Ok(GameContract::Clubs),
+            "betl" => Ok(GameContract::Betl),
+            "sans" => Ok(GameContract::Sans),
+            _ => Err(NotationError::InvalidContract(s.into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card(s: &str) -> Card {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn every_card_round_trips() {
+        for card in CardSet::DECK {
+            assert_eq!(card.to_string().parse::<Card>(), Ok(card));
+            assert_eq!(format!("{card:#}").parse::<Card>(), Ok(card));
+        }
+    }
+
+    #[test]
+    fn cards_parse_in_any_spelling() {
+        let ten_of_hearts = Card {
+            suit: CardSuit::Hearts,
+            value: CardValue::Ten,
+        };
+        for s in ["10♥", "10h", "10H", "Th", "tH"] {
+            assert_eq!(card(s), ten_of_hearts);
+        }
+
+        assert_eq!(card("KD"), card("K♦"));
+        assert_eq!(card("a♠").to_string(), "A♠");
+        assert_eq!(format!("{:#}", card("7♣")), "7C");
+    }
+
+    #[test]
+    fn invalid_cards_are_rejected() {
+        for s in ["", "A", "♠", "1♠", "11♠", "AX", "A♠♠", "B♦"] {
+            assert_eq!(s.parse::<Card>(), Err(NotationError::InvalidCard(s.into())));
+        }
+    }
+
+    #[test]
+    fn hands_are_grouped_by_suit_highest_first() {
+        let hand: CardSet = "♣987 ♠10KA ♦J7Q".parse().unwrap();
+
+        assert_eq!(hand.len(), 9);
+        assert_eq!(hand.to_string(), "♠AK10 ♦QJ7 ♥- ♣987");
+        assert_eq!(format!("{hand:#}"), "SAK10 DQJ7 H- C987");
+        assert_eq!(CardSet::EMPTY.to_string(), "♠- ♦- ♥- ♣-");
+    }
+
+    #[test]
+    fn hands_round_trip() {
+        for hand in CardsInPlay::deal_random().hands {
+            assert_eq!(hand.to_string().parse::<CardSet>(), Ok(hand));
+            assert_eq!(format!("{hand:#}").parse::<CardSet>(), Ok(hand));
+        }
+        assert_eq!(CardSet::EMPTY.to_string().parse(), Ok(CardSet::EMPTY));
+    }
+
+    #[test]
+    fn invalid_hands_are_rejected() {
+        assert_eq!(
+            "♠AKA".parse::<CardSet>(),
+            Err(NotationError::DuplicateCard(card("A♠")))
+        );
+        assert_eq!(
+            "X987".parse::<CardSet>(),
+            Err(NotationError::InvalidHandGroup("X987".into()))
+        );
+        assert_eq!(
+            "♠A1".parse::<CardSet>(),
+            Err(NotationError::InvalidValue("1".into()))
+        );
+    }
+
+    #[test]
+    fn deals_round_trip() {
+        for _ in 0..100 {
+            let deal = CardsInPlay::deal_random();
+            for notation in [deal.to_string(), format!("{deal:#}")] {
+                let parsed: CardsInPlay = notation.parse().unwrap();
+                assert_eq!(parsed.hands, deal.hands);
+                assert_eq!(parsed.hidden, deal.hidden);
+            }
+        }
+    }
+
+    #[test]
+    fn deal_notation() {
+        let notation = "♠AKQJ10 ♦AK ♥A ♣AK / ♠987 ♦QJ10 ♥KQJ ♣Q / ♠- ♦987 ♥1098 ♣J1098 / 7♥ 7♣";
+        let deal: CardsInPlay = notation.parse().unwrap();
+
+        assert_eq!(deal.hands[0].in_suit(CardSuit::Spades).len(), 5);
+        assert_eq!(deal.hidden, [card("7♥"), card("7♣")]);
+        assert_eq!(deal.to_string(), notation);
+    }
+
+    #[test]
+    fn invalid_deals_are_rejected() {
+        let hands = "♠AKQJ10 ♦AK ♥A ♣AK / ♠987 ♦QJ10 ♥KQJ ♣Q / ♠- ♦987 ♥1098 ♣J1098";
+
+        assert_eq!(
+            hands.parse::<CardsInPlay>().map(|_| ()),
+            Err(NotationError::DealParts(3))
+        );
+        assert_eq!(
+            format!("{hands} / 7♥").parse::<CardsInPlay>().map(|_| ()),
+            Err(NotationError::CardCount {
+                expected: 2,
+                found: 1
+            })
+        );
+        assert_eq!(
+            format!("{hands} / 7♥ A♠")
+                .parse::<CardsInPlay>()
+                .map(|_| ()),
+            Err(NotationError::DuplicateCard(card("A♠")))
+        );
+        assert_eq!(
+            "♠AKQJ10 ♦AK ♥A ♣AK / ♠AKQJ10 ♦AK ♥A ♣AK / ♠- ♦987 ♥1098 ♣J1098 / 7♥ 7♣"
+                .parse::<CardsInPlay>()
+                .map(|_| ()),
+            Err(NotationError::DuplicateCard(card("10♠")))
+        );
+    }
+
+    #[test]
+    fn contracts_round_trip() {
+        use GameContract::*;
+
+        for contract in [Spades, Diamonds, Hearts, Clubs, Betl, Sans] {
+            assert_eq!(contract.to_string().parse(), Ok(contract));
+        }
+        assert_eq!("SANS".parse(), Ok(Sans));
+        assert_eq!(
+            "Misere".parse::<GameContract>(),
+            Err(NotationError::InvalidContract("Misere".into()))
+        );
+    }
+}
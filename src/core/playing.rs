@@ -1,4 +1,5 @@
 use crate::core::{
+    card_set::CardSet,
     types::GameContract,
     types::{Card, CardSuit},
 };
@@ -7,6 +8,9 @@ use super::{
     actions::{GameAction, GameActionKind},
     choosing::{ContreLevel, PlayerResponseState},
     game::{Game, GameError, GameState, get_third, turn_inc},
+    ledger::LedgerEntry,
+    ruleset::DealerSoups,
+    solver::Solver,
     types::GameContractData,
 };
 
@@ -20,6 +24,39 @@ pub struct PlayingState {
     responses: [PlayerResponseState; 3],
     tricks: [u32; 3],
     round: RoundState,
+    /// Hands lying face up on the table.
+    #[serde(default)]
+    exposed: [bool; 3],
+    /// Who has confirmed the tricks, once the last trick of an honor mode hand is in.
+    #[serde(default)]
+    review: Option<[bool; 3]>,
+    /// Player whose revoke was called, ending the hand.
+    #[serde(default)]
+    revoke: Option<usize>,
+    /// Claim or concession waiting on the other players.
+    #[serde(default)]
+    claim: Option<PendingClaim>,
+}
+
+/// Offer to end the hand with the tricks left shared out without playing them.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct PendingClaim {
+    /// Player who claimed or conceded.
+    pub by: usize,
+    /// How many of the tricks left go to the declarer.
+    pub declarer_tricks: u32,
+    /// Who has agreed so far.
+    pub accepted: [bool; 3],
+}
+
+/// A card played in honor mode and whether the rules allowed it.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct Play {
+    pub player: usize,
+    pub card: Card,
+    /// Trick the card was played to, counting from 0.
+    pub trick: u32,
+    pub legal: bool,
 }
 
 impl PlayingState {
@@ -36,9 +73,46 @@ impl PlayingState {
             responses: player_responses,
             tricks: Default::default(),
             round: RoundState::default(),
+            exposed: Default::default(),
+            review: None,
+            revoke: None,
+            claim: None,
         }
     }
 
+    pub fn contract(&self) -> GameContractData {
+        self.contract
+    }
+
+    pub fn contre_level(&self) -> ContreLevel {
+        self.contre_level
+    }
+
+    pub fn declarer(&self) -> usize {
+        self.declarer
+    }
+
+    pub fn tricks(&self) -> &[u32; 3] {
+        &self.tricks
+    }
+
+    pub fn exposed(&self) -> &[bool; 3] {
+        &self.exposed
+    }
+
+    /// Confirmations so far, while an honor mode hand waits for everyone to accept it.
+    pub fn review(&self) -> Option<&[bool; 3]> {
+        self.review.as_ref()
+    }
+
+    pub fn claim(&self) -> Option<&PendingClaim> {
+        self.claim.as_ref()
+    }
+
+    fn is_open_contract(&self) -> bool {
+        matches!(self.contract.value, GameContract::Betl | GameContract::Sans)
+    }
+
     fn trump(&self) -> Option<CardSuit> {
         match self.contract.value {
             GameContract::Spades => Some(CardSuit::Spades),
@@ -50,6 +124,10 @@ impl PlayingState {
         }
     }
 
+    fn are_opponents(&self, a: usize, b: usize) -> bool {
+        (a == self.declarer) != (b == self.declarer)
+    }
+
     fn declarer_tricks(&self) -> u32 {
         self.tricks[self.declarer]
     }
@@ -64,20 +142,48 @@ impl PlayingState {
     fn total_tricks(&self) -> u32 {
         self.tricks.iter().sum()
     }
+
+    /// Tricks not taken yet, the one on the table included.
+    fn tricks_left(&self) -> u32 {
+        10 - self.total_tricks()
+    }
+
+    /// Cards are still being played: nobody is waiting on a claim or checking the tricks.
+    fn is_in_play(&self) -> bool {
+        self.claim.is_none() && self.review.is_none()
+    }
 }
 
-#[derive(Debug, Default, Deserialize, Serialize)]
+#[derive(Debug, Default, Clone, Copy, Deserialize, Serialize)]
 pub struct RoundState {
     played: [Option<Card>; 3],
     lead_suit: Option<CardSuit>,
 }
 
 impl RoundState {
-    fn is_round_over(&self) -> bool {
+    /// A trick whose suit was set before anyone played, like the talon tricks of an all-pass
+    /// play-out.
+    pub(super) fn led_with(suit: CardSuit) -> Self {
+        Self {
+            played: [None; 3],
+            lead_suit: Some(suit),
+        }
+    }
+
+    pub(super) fn lead_suit(&self) -> Option<CardSuit> {
+        self.lead_suit
+    }
+
+    pub(super) fn is_round_over(&self) -> bool {
         self.played.iter().all(Option::is_some)
     }
 
-    fn winner(&self, trump: Option<CardSuit>) -> usize {
+    /// Cards on the table.
+    pub(super) fn cards(&self) -> CardSet {
+        self.played.iter().flatten().copied().collect()
+    }
+
+    pub(super) fn winner(&self, trump: Option<CardSuit>) -> usize {
         if let Some(trump_suit) = trump {
             if let Some(trump_winner) = self.highest_in_suit(trump_suit) {
                 return trump_winner;
@@ -89,7 +195,7 @@ impl RoundState {
             .expect("At least one card in lead suit")
     }
 
-    fn highest_in_suit(&self, suit: CardSuit) -> Option<usize> {
+    pub(super) fn highest_in_suit(&self, suit: CardSuit) -> Option<usize> {
         self.played
             .iter()
             .enumerate()
@@ -98,7 +204,7 @@ impl RoundState {
             .map(|(i, _)| i)
     }
 
-    fn play_card(mut self, card: Card, player: usize) -> Self {
+    pub(super) fn play_card(mut self, card: Card, player: usize) -> Self {
         debug_assert_eq!(
             self.played[player], None,
             "Should not be able to play card twice"
@@ -119,96 +225,314 @@ impl Game<PlayingState> {
 
         match action.kind {
             GameActionKind::PlayCard(card) => Ok(self.play_card(card)),
+            GameActionKind::LayOpen => Ok(self.lay_open(action.player)),
+            GameActionKind::CallRevoke => Ok(self.call_revoke(action.player)),
+            GameActionKind::ConfirmHand => Ok(self.confirm_hand(action.player)),
+            GameActionKind::Claim { tricks } => Ok(self.offer_claim(action.player, tricks)),
+            GameActionKind::Concede => Ok(self.concede(action.player)),
+            GameActionKind::AcceptClaim => Ok(self.accept_claim(action.player)),
+            GameActionKind::RejectClaim => Ok(self.reject_claim()),
             _ => Err(GameError::InvalidAction),
         }
     }
 
-    fn validate(&self, action: &GameAction) -> Result<(), GameError> {
-        self.validate_turn(action)?;
+    /// Hands lying face up, by hand position.
+    pub fn exposed_hands(&self) -> impl Iterator<Item = (usize, CardSet)> + '_ {
+        (0..3)
+            .filter(|&player| self.state.exposed[player])
+            .map(|player| (player, self.cards.hands[player]))
+    }
 
+    fn validate(&self, action: &GameAction) -> Result<(), GameError> {
         match action.kind {
-            GameActionKind::PlayCard(card) => self.validate_play_card(action.player, card),
+            GameActionKind::PlayCard(card) => {
+                if !self.state.is_in_play() {
+                    return Err(GameError::InvalidAction);
+                }
+                self.validate_turn(action)?;
+                self.validate_play_card(action.player, card)
+            }
+            // Cards can be laid open and revokes called at any point, not only on your turn
+            GameActionKind::LayOpen => self.validate_lay_open(action.player),
+            GameActionKind::CallRevoke => self.validate_call_revoke(action.player),
+            GameActionKind::ConfirmHand => self.validate_confirm_hand(action.player),
+            GameActionKind::Claim { tricks } => self.validate_claim(action.player, tricks),
+            GameActionKind::Concede => self.validate_concede(),
+            GameActionKind::AcceptClaim | GameActionKind::RejectClaim => {
+                self.validate_claim_response(action.player)
+            }
             _ => Err(GameError::InvalidAction),
         }
     }
 
+    fn validate_lay_open(&self, player: usize) -> Result<(), GameError> {
+        let declarer = self.state.declarer;
+        let defenders = [turn_inc(declarer), turn_inc(turn_inc(declarer))];
+        let both_defend = defenders
+            .iter()
+            .all(|&d| self.state.responses[d] != PlayerResponseState::Rejected);
+
+        if self.rules.open_play
+            && self.state.is_open_contract()
+            && player != declarer
+            && both_defend
+            && !self.state.exposed[player]
+            && self.state.is_in_play()
+        {
+            Ok(())
+        } else {
+            Err(GameError::BadAction)
+        }
+    }
+
+    fn lay_open(mut self, player: usize) -> GameState {
+        self.state.exposed[player] = true;
+        GameState::Playing(self)
+    }
+
     fn validate_play_card(&self, player: usize, card: Card) -> Result<(), GameError> {
         if !self.player_has_card(player, card) {
             return Err(GameError::BadAction);
         }
 
-        if self.no_cards_played() || self.is_round_suit(card) {
-            return Ok(());
-        } else if self.has_trump(player) {
-            return self.validate_trump(card);
+        // In honor mode any card goes; revokes are called out afterwards
+        if self.rules.honor_mode || self.is_legal_play(player, card) {
+            Ok(())
+        } else {
+            Err(GameError::BadAction)
         }
-
-        Ok(())
     }
 
     fn player_has_card(&self, player: usize, card: Card) -> bool {
-        self.cards.hands[player].contains(&card)
+        self.cards.hands[player].contains(card)
     }
 
-    fn no_cards_played(&self) -> bool {
-        let is_no_cards = self.state.round.lead_suit.is_none();
+    /// Follow the lead suit if you can, otherwise trump if you can.
+    fn is_legal_play(&self, player: usize, card: Card) -> bool {
+        let hand = self.cards.hands[player];
+        match self.state.round.lead_suit {
+            None => true,
+            Some(suit) if hand.has_suit(suit) => card.suit == suit,
+            Some(_) => match self.state.trump() {
+                Some(trump) if hand.has_suit(trump) => card.suit == trump,
+                _ => true,
+            },
+        }
+    }
 
-        if is_no_cards {
-            for card in &self.state.round.played {
-                debug_assert!(
-                    card.is_none(),
-                    "No cards should be played if round state suit is none"
-                )
-            }
+    fn validate_call_revoke(&self, player: usize) -> Result<(), GameError> {
+        if self.rules.honor_mode && self.find_revoke(player).is_some() {
+            Ok(())
+        } else {
+            Err(GameError::BadAction)
         }
+    }
+
+    /// Player on the other side from `caller` who played an illegal card in a finished trick.
+    fn find_revoke(&self, caller: usize) -> Option<usize> {
+        let finished = self.state.total_tricks();
+        self.plays
+            .iter()
+            .find(|play| {
+                !play.legal
+                    && play.trick < finished
+                    && self.state.are_opponents(caller, play.player)
+            })
+            .map(|play| play.player)
+    }
 
-        is_no_cards
+    fn call_revoke(mut self, caller: usize) -> GameState {
+        let offender = self
+            .find_revoke(caller)
+            .expect("Revoke checked in validate");
+        self.apply_revoke_penalty(offender);
+        self.state.revoke = Some(offender);
+        self.finish_hand()
     }
 
-    fn is_round_suit(&self, card: Card) -> bool {
-        if let Some(suit) = self.state.round.lead_suit.as_ref() {
-            *suit == card.suit
+    /// Sets the tricks so the side that revoked loses the hand as badly as it can.
+    fn apply_revoke_penalty(&mut self, offender: usize) {
+        let declarer = self.state.declarer;
+        let is_betl = self.state.contract.value == GameContract::Betl;
+        let declarer_lost = offender == declarer;
+
+        // All ten tricks are handed out; a single one is enough to sink a betl
+        let (declarer_tricks, defender_tricks) = match (declarer_lost, is_betl) {
+            (true, true) => (1, [5, 4]),
+            (true, false) | (false, true) => (0, [5, 5]),
+            (false, false) => (10, [0, 0]),
+        };
+        let defenders = [turn_inc(declarer), turn_inc(turn_inc(declarer))];
+
+        self.state.tricks[declarer] = declarer_tricks;
+        for (defender, tricks) in defenders.into_iter().zip(defender_tricks) {
+            self.state.tricks[defender] = tricks;
+        }
+        self.state.round = RoundState::default();
+    }
+
+    fn validate_claim(&self, player: usize, tricks: u32) -> Result<(), GameError> {
+        if player != self.state.declarer
+            || !self.state.is_in_play()
+            || tricks > self.state.tricks_left()
+        {
+            return Err(GameError::BadAction);
+        }
+
+        if self.rules.ranked && !self.is_claim_sure(tricks) {
+            return Err(GameError::BadAction);
+        }
+
+        Ok(())
+    }
+
+    /// Whether the declarer takes `tricks` of those left however the defenders play. In betl
+    /// that is no more than `tricks`.
+    fn is_claim_sure(&self, tricks: u32) -> bool {
+        let is_betl = self.state.contract.value == GameContract::Betl;
+        let need = if is_betl {
+            self.state.tricks_left() - tricks
         } else {
-            false
+            tricks
+        };
+
+        Solver::new(self.state.trump(), self.state.declarer, is_betl).secures(
+            self.cards.hands,
+            self.state.round,
+            self.turn,
+            need,
+        )
+    }
+
+    fn validate_concede(&self) -> Result<(), GameError> {
+        if self.state.is_in_play() {
+            Ok(())
+        } else {
+            Err(GameError::BadAction)
         }
     }
 
-    fn has_trump(&self, player: usize) -> bool {
-        if let Some(trump_suit) = self.state.trump().as_ref() {
-            let trump_card = self.cards.hands[player]
-                .iter()
-                .find(|card| card.suit == *trump_suit);
+    fn validate_claim_response(&self, player: usize) -> Result<(), GameError> {
+        match self.state.claim {
+            Some(claim) if self.must_accept(&claim, player) && !claim.accepted[player] => Ok(()),
+            Some(_) => Err(GameError::BadAction),
+            None => Err(GameError::InvalidAction),
+        }
+    }
+
+    /// Everyone but the one who made the offer has to agree to it, a defender who passed
+    /// included since their cards are still in play.
+    fn must_accept(&self, claim: &PendingClaim, player: usize) -> bool {
+        player != claim.by
+    }
+
+    fn offer_claim(mut self, player: usize, tricks: u32) -> GameState {
+        self.state.claim = Some(PendingClaim {
+            by: player,
+            declarer_tricks: tricks,
+            accepted: Default::default(),
+        });
+        GameState::Playing(self)
+    }
+
+    /// Conceding the declarer's side takes nothing more, or in betl everything left, and the
+    /// other way round for the defenders.
+    fn concede(self, player: usize) -> GameState {
+        let is_betl = self.state.contract.value == GameContract::Betl;
+        let declarer_tricks = if (player == self.state.declarer) == is_betl {
+            self.state.tricks_left()
+        } else {
+            0
+        };
+
+        self.offer_claim(player, declarer_tricks)
+    }
 
-            trump_card.is_some()
+    fn accept_claim(mut self, player: usize) -> GameState {
+        let mut claim = self.state.claim.take().expect("Claim checked in validate");
+        claim.accepted[player] = true;
+
+        if (0..3).all(|p| claim.accepted[p] || !self.must_accept(&claim, p)) {
+            self.settle_claim(claim)
         } else {
-            false
+            self.state.claim = Some(claim);
+            GameState::Playing(self)
         }
     }
 
-    fn validate_trump(&self, card: Card) -> Result<(), GameError> {
-        if let Some(trump_suit) = self.state.trump().as_ref() {
-            if *trump_suit == card.suit {
-                Ok(())
-            } else {
-                Err(GameError::BadAction)
-            }
+    fn reject_claim(mut self) -> GameState {
+        self.state.claim = None;
+        GameState::Playing(self)
+    }
+
+    /// Shares out the tricks left as agreed and scores the hand.
+    fn settle_claim(mut self, claim: PendingClaim) -> GameState {
+        self.share_claimed_tricks(&claim);
+        self.finish_hand()
+    }
+
+    /// The defenders' tricks go to the defenders playing against the contract, split as evenly
+    /// as they can be starting on the declarer's left. One who passed takes none, as they'd
+    /// otherwise be scored against the one defending.
+    fn share_claimed_tricks(&mut self, claim: &PendingClaim) {
+        let declarer = self.state.declarer;
+        let defenders: Vec<usize> = [turn_inc(declarer), turn_inc(turn_inc(declarer))]
+            .into_iter()
+            .filter(|&p| self.state.responses[p] != PlayerResponseState::Rejected)
+            .collect();
+
+        let defender_tricks = self.state.tricks_left() - claim.declarer_tricks;
+        self.state.tricks[declarer] += claim.declarer_tricks;
+        for i in 0..defender_tricks as usize {
+            self.state.tricks[defenders[i % defenders.len()]] += 1;
+        }
+        self.state.round = RoundState::default();
+    }
+
+    fn validate_confirm_hand(&self, player: usize) -> Result<(), GameError> {
+        match self.state.review {
+            Some(confirmed) if !confirmed[player] => Ok(()),
+            Some(_) => Err(GameError::BadAction),
+            None => Err(GameError::InvalidAction),
+        }
+    }
+
+    fn confirm_hand(mut self, player: usize) -> GameState {
+        let confirmed = self
+            .state
+            .review
+            .as_mut()
+            .expect("Review checked in validate");
+        confirmed[player] = true;
+
+        if confirmed.iter().all(|&x| x) {
+            self.finish_hand()
         } else {
-            Err(GameError::BadAction)
+            GameState::Playing(self)
         }
     }
 
     fn play_card(mut self, card: Card) -> GameState {
+        if self.rules.honor_mode {
+            self.record_play(card);
+        }
         self.state.round = self.state.round.play_card(card, self.turn);
         self.remove_card_from_hand(card);
 
         self.to_next()
     }
 
+    fn record_play(&mut self, card: Card) {
+        self.plays.push(Play {
+            player: self.turn,
+            card,
+            trick: self.state.total_tricks(),
+            legal: self.is_legal_play(self.turn, card),
+        });
+    }
+
     fn remove_card_from_hand(&mut self, card: Card) {
-        let hand = &mut self.cards.hands[self.turn];
-        if let Some(pos) = hand.iter().position(|&c| c == card) {
-            hand.swap_remove(pos);
-        }
+        self.cards.hands[self.turn].remove(card);
     }
 
     fn to_next(mut self) -> GameState {
@@ -222,40 +546,55 @@ impl Game<PlayingState> {
     }
 
     fn to_next_after_round(mut self) -> GameState {
-        if self.is_hand_over() {
-            let first = self.first;
-            self.compute_scores();
-            GameState::Bidding(Game::new_starting_state(
-                turn_inc(first),
-                self.score,
-                self.refas,
-            ))
-        } else {
+        if !self.is_hand_over() {
             GameState::Playing(self)
+        } else if self.rules.honor_mode {
+            // Revokes can still be called until everyone accepts the tricks
+            self.state.review = Some([false; 3]);
+            GameState::Playing(self)
+        } else {
+            self.finish_hand()
         }
     }
 
+    fn finish_hand(mut self) -> GameState {
+        self.compute_scores();
+        GameState::Bidding(self.into_next_hand())
+    }
+
     fn is_hand_over(&self) -> bool {
         match self.state.contract.value {
-            GameContract::Betl => self.state.declarer_tricks() > 0,
+            GameContract::Betl => {
+                self.state.declarer_tricks() > 0 || self.state.total_tricks() == 10
+            }
             _ => self.state.total_tricks() == 10 || self.state.responder_tricks() >= 5,
         }
     }
 
+    /// A defender who passed still plays their cards, since every trick takes three.
     fn next_turn(&self) -> usize {
-        let mut turn = turn_inc(self.turn);
-        for _ in 0..3 {
-            if self.state.responses[turn] != PlayerResponseState::Rejected {
-                return turn;
-            }
+        turn_inc(self.turn)
+    }
 
-            turn = turn_inc(turn);
-        }
+    fn compute_scores(&mut self) {
+        let declarer = self.seat_of(self.state.declarer);
+        let score_before = self.score.clone();
+        self.update_scores();
+        let refa = self.play_refa(declarer, &score_before);
 
-        panic!("There should be at least one more person who has not passed bid.");
+        let mut entry = LedgerEntry::new(
+            self.hand,
+            declarer,
+            self.state.contract,
+            self.state.contre_level,
+            refa,
+        );
+        entry.revoke = self.state.revoke.map(|player| self.seat_of(player));
+        self.ledger
+            .push(entry.with_changes(&score_before, &self.score));
     }
 
-    fn compute_scores(&mut self) {
+    fn update_scores(&mut self) {
         self.update_declarer_score();
         let responders = [
             turn_inc(self.state.declarer),
@@ -276,17 +615,46 @@ impl Game<PlayingState> {
         for responder in responders {
             self.update_responder_score(responder);
         }
-    }
 
-    fn update_declarer_score(&mut self) {
-        let declarer_score = &mut self.score[self.state.declarer];
+        self.update_dealer_score();
+    }
 
-        let pass_condition = match self.state.contract.value {
+    fn is_contract_passed(&self) -> bool {
+        match self.state.contract.value {
             GameContract::Betl => self.state.tricks[self.state.declarer] == 0,
             _ => self.state.tricks[self.state.declarer] >= 6,
-        };
+        }
+    }
+
+    fn update_declarer_score(&mut self) {
+        let pass_condition = self.is_contract_passed();
+        let declarer = self.seat_of(self.state.declarer);
 
-        declarer_score.apply_result(self.state.contract, pass_condition, self.state.contre_level)
+        self.score[declarer].apply_result(
+            self.state.contract,
+            pass_condition,
+            self.state.contre_level,
+        )
+    }
+
+    /// The dealer sitting out only scores when the ruleset gives them something.
+    fn update_dealer_score(&mut self) {
+        if !self.rules.dealer_sits_out() || self.is_contract_passed() {
+            return;
+        }
+
+        match self.rules.dealer_soups {
+            DealerSoups::None => {}
+            DealerSoups::Consolation => {
+                let declarer = self.seat_of(self.state.declarer);
+                self.score[self.dealer].apply_soups(
+                    self.state.contract,
+                    1,
+                    declarer,
+                    self.state.contre_level,
+                );
+            }
+        }
     }
 
     fn update_responder_score(&mut self, responder: usize) {
@@ -300,7 +668,9 @@ impl Game<PlayingState> {
         let partner_tricks = self.state.tricks[partner];
         let total_tricks = responder_tricks + partner_tricks;
 
-        let score = &mut self.score[responder];
+        let target = self.seat_of(declarer);
+        let seat = self.seat_of(responder);
+        let score = &mut self.score[seat];
 
         match responder_state {
             NoResponse => panic!("Responder should not be in No Response state"),
@@ -311,18 +681,17 @@ impl Game<PlayingState> {
             Caller => {
                 // Caller takes credit for both players' tricks, must get 4 total
                 let passed = total_tricks >= 4;
-                let soups = if declarer > partner { 1 } else { 0 };
 
                 score.apply_soups(
                     self.state.contract,
                     responder_tricks,
-                    soups,
+                    target,
                     self.state.contre_level,
                 );
                 score.apply_soups(
                     self.state.contract,
                     partner_tricks,
-                    soups,
+                    target,
                     self.state.contre_level,
                 );
 
@@ -334,18 +703,17 @@ impl Game<PlayingState> {
             Contrer => {
                 // Contrer must get 5 tricks in total (with the Called partner), and scores all rewards
                 let passed = total_tricks >= 5;
-                let soups = if declarer > partner { 1 } else { 0 };
 
                 score.apply_soups(
                     self.state.contract,
                     responder_tricks,
-                    soups,
+                    target,
                     self.state.contre_level,
                 );
                 score.apply_soups(
                     self.state.contract,
                     partner_tricks,
-                    soups,
+                    target,
                     self.state.contre_level,
                 );
 
@@ -357,12 +725,11 @@ impl Game<PlayingState> {
             Accepted => {
                 // Normal case: pass if took 2 tricks OR combined 4
                 let passed = responder_tricks >= 2 || total_tricks >= 4;
-                let soups = if declarer > partner { 1 } else { 0 };
 
                 score.apply_soups(
                     self.state.contract,
                     responder_tricks,
-                    soups,
+                    target,
                     self.state.contre_level,
                 );
 
@@ -378,5 +745,284 @@ impl Game<PlayingState> {
         self.state.tricks[winner] += 1;
         self.state.round = RoundState::default();
         self.turn = winner;
+
+        if self.rules.open_play && self.state.contract.value == GameContract::Betl {
+            self.state.exposed[self.state.declarer] = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{
+        actions::CardChoice,
+        bidding::bidding::BiddingState,
+        game::{CardsInPlay, new_game},
+        ruleset::Ruleset,
+    };
+
+    const DEAL: &str = "♠AKQJ10 ♦AK ♥A ♣AK / ♠987 ♦QJ10 ♥KQJ ♣Q / ♠- ♦987 ♥1098 ♣J1098 / 7♥ 7♣";
+
+    fn card(s: &str) -> Card {
+        s.parse().unwrap()
+    }
+
+    /// Has whoever's turn it is take `kind`.
+    fn act(state: GameState, kind: GameActionKind) -> GameState {
+        let turn = match &state {
+            GameState::Bidding(game) => game.turn,
+            GameState::ChoosingCards(game) => game.turn,
+            GameState::ChoosingContract(game) => game.turn,
+            GameState::RespondingToContract(game) => game.turn,
+            GameState::HelpOrContreToContract(game) => game.turn,
+            GameState::Playing(game) => game.turn,
+            _ => panic!("Not a state these tests reach"),
+        };
+        state.apply(GameAction::new(turn, kind)).unwrap()
+    }
+
+    /// Position 0 declares diamonds on `DEAL`, position 1 passes and position 2 defends alone.
+    fn one_defender() -> Game<PlayingState> {
+        one_defender_at(Ruleset::default())
+    }
+
+    /// [`one_defender`] at a table playing by `rules`, seat 0 opening the bidding.
+    fn one_defender_at(rules: Ruleset) -> Game<PlayingState> {
+        let GameState::Bidding(mut game) = new_game(0, 30, 0, rules) else {
+            unreachable!()
+        };
+        game.cards = DEAL.parse::<CardsInPlay>().unwrap();
+
+        let mut state = GameState::Bidding(game);
+        for kind in [
+            GameActionKind::Bid,
+            GameActionKind::PassBid,
+            GameActionKind::PassBid,
+            GameActionKind::ChooseCards(CardChoice {
+                take_cards: vec![card("7♥"), card("7♣")],
+                discard_cards: vec![card("A♦"), card("K♦")],
+            }),
+            GameActionKind::ChooseContract(GameContract::Diamonds),
+            GameActionKind::RejectContract,
+            GameActionKind::AcceptContract,
+        ] {
+            state = act(state, kind);
+        }
+        while let GameState::HelpOrContreToContract(_) = state {
+            state = act(state, GameActionKind::PassHelpContre);
+        }
+
+        match state {
+            GameState::Playing(game) => game,
+            _ => panic!("The hand is played"),
+        }
+    }
+
+    #[test]
+    fn a_defender_who_passed_plays_their_cards() {
+        let game = one_defender();
+        assert_eq!(game.state.responses[1], PlayerResponseState::Rejected);
+
+        let mut state = GameState::Playing(game);
+        for _ in 0..3 {
+            let GameState::Playing(game) = &state else {
+                panic!("The trick is still open");
+            };
+            let card = game.cards.hands[game.turn]
+                .iter()
+                .find(|&card| game.validate_play_card(game.turn, card).is_ok())
+                .unwrap();
+            state = act(state, GameActionKind::PlayCard(card));
+        }
+
+        let GameState::Playing(game) = state else {
+            panic!("Nine tricks are left");
+        };
+        assert_eq!(game.state.total_tricks(), 1);
+        assert!(game.cards.hands.iter().all(|hand| hand.len() == 9));
+    }
+
+    #[test]
+    fn a_defender_who_passed_answers_claims() {
+        let game = one_defender();
+        let claim = GameAction::new(0, GameActionKind::Claim { tricks: 8 });
+        let GameState::Playing(game) = game.apply(claim).unwrap() else {
+            panic!("The claim waits for the defenders");
+        };
+
+        let accept = |player| GameAction::new(player, GameActionKind::AcceptClaim);
+        let GameState::Playing(game) = game.apply(accept(2)).unwrap() else {
+            panic!("The defender who passed hasn't agreed yet");
+        };
+        assert!(game.state.claim().is_some());
+
+        let mut shared = one_defender();
+        shared.share_claimed_tricks(game.state.claim().unwrap());
+        // Both tricks left to the defenders go to the one defending
+        assert_eq!(shared.state.tricks, [8, 0, 2]);
+
+        let GameState::Bidding(game) = game.apply(accept(1)).unwrap() else {
+            panic!("The hand is scored");
+        };
+        let entry = game.ledger.entries().last().unwrap();
+        assert!(entry.soups[2][0] > 0);
+        assert!(entry.soups[1].iter().all(|&soups| soups == 0));
+        // Two tricks are enough for the defender to get away without bula
+        assert_eq!(entry.bula[1], 0);
+        assert_eq!(entry.bula[2], 0);
+    }
+
+    /// Position 0 declares betl on a hand without spades. Position 2 defends, and position 1
+    /// too when `both_defend`.
+    fn betl(open_play: bool, both_defend: bool) -> Game<PlayingState> {
+        let rules = Ruleset {
+            open_play,
+            ..Ruleset::default()
+        };
+        let GameState::Bidding(mut game) = new_game(0, 30, 0, rules) else {
+            unreachable!()
+        };
+        game.cards = "♠- ♦987 ♥1098 ♣J1098 / ♠AKQJ10 ♦AK ♥A ♣AK / ♠987 ♦QJ10 ♥KQJ ♣Q / 7♥ 7♣"
+            .parse::<CardsInPlay>()
+            .unwrap();
+
+        let mut state = GameState::Bidding(game);
+        for kind in [
+            GameActionKind::Bid,
+            GameActionKind::PassBid,
+            GameActionKind::PassBid,
+            GameActionKind::ChooseCards(CardChoice {
+                take_cards: vec![card("7♥"), card("7♣")],
+                discard_cards: vec![card("J♣"), card("10♣")],
+            }),
+            GameActionKind::ChooseContract(GameContract::Betl),
+            if both_defend {
+                GameActionKind::AcceptContract
+            } else {
+                GameActionKind::RejectContract
+            },
+            GameActionKind::AcceptContract,
+        ] {
+            state = act(state, kind);
+        }
+        while let GameState::HelpOrContreToContract(_) = state {
+            state = act(state, GameActionKind::PassHelpContre);
+        }
+
+        match state {
+            GameState::Playing(game) => game,
+            _ => panic!("The hand is played"),
+        }
+    }
+
+    #[test]
+    fn the_betl_hand_is_shown_after_the_first_trick() {
+        let game = betl(true, true);
+        let declarer_hand = game.cards.hands[0];
+        assert_eq!(game.exposed_hands().count(), 0);
+
+        // Spades are led, which the declarer can't follow and so can't take
+        let mut state = GameState::Playing(game);
+        for _ in 0..3 {
+            let GameState::Playing(game) = &state else {
+                panic!("The trick is still open");
+            };
+            let card = game.cards.hands[game.turn]
+                .iter()
+                .find(|&card| game.validate_play_card(game.turn, card).is_ok())
+                .unwrap();
+            state = act(state, GameActionKind::PlayCard(card));
+        }
+
+        let GameState::Playing(game) = state else {
+            panic!("The declarer hasn't taken a trick");
+        };
+        assert_eq!(game.state.tricks()[0], 0);
+        let exposed: Vec<_> = game.exposed_hands().collect();
+        assert_eq!(exposed, vec![(0, game.cards.hands[0])]);
+        assert!(
+            game.cards.hands[0]
+                .iter()
+                .all(|card| declarer_hand.contains(card))
+        );
+    }
+
+    #[test]
+    fn defenders_lay_open_under_open_play_only() {
+        let lay_open = |player| GameAction::new(player, GameActionKind::LayOpen);
+
+        let game = betl(false, true);
+        assert!(matches!(
+            game.validate(&lay_open(1)),
+            Err(GameError::BadAction)
+        ));
+
+        let game = betl(true, true);
+        assert!(matches!(
+            game.validate(&lay_open(0)),
+            Err(GameError::BadAction)
+        ));
+        // Out of turn too
+        assert_ne!(game.turn, 2);
+        let GameState::Playing(game) = game.apply(lay_open(2)).unwrap() else {
+            panic!("Laying cards open doesn't end the hand");
+        };
+        let exposed: Vec<_> = game.exposed_hands().collect();
+        assert_eq!(exposed, vec![(2, game.cards.hands[2])]);
+        assert!(matches!(
+            game.validate(&lay_open(2)),
+            Err(GameError::BadAction)
+        ));
+
+        // Not when a defender passed and the other defends alone
+        let game = betl(true, false);
+        assert!(matches!(
+            game.validate(&lay_open(2)),
+            Err(GameError::BadAction)
+        ));
+    }
+
+    /// Has the declarer claim five tricks, going down, and both defenders accept.
+    fn go_down(game: Game<PlayingState>) -> Game<BiddingState> {
+        let mut state = GameState::Playing(game);
+        for (player, kind) in [
+            (0, GameActionKind::Claim { tricks: 5 }),
+            (1, GameActionKind::AcceptClaim),
+            (2, GameActionKind::AcceptClaim),
+        ] {
+            state = state.apply(GameAction::new(player, kind)).unwrap();
+        }
+
+        match state {
+            GameState::Bidding(game) => game,
+            _ => panic!("The claim ends the hand"),
+        }
+    }
+
+    #[test]
+    fn the_dealer_sitting_out_scores_by_the_ruleset() {
+        let rules = Ruleset {
+            seats: 4,
+            ..Ruleset::default()
+        };
+        let game = one_defender_at(rules);
+        assert_eq!(game.dealer, 3);
+        assert_eq!(game.seat_of(game.state.declarer), 0);
+
+        let game = go_down(game);
+        assert_eq!(game.score[3].total_soups(), 0);
+        assert_eq!(game.score[3].bulls(), 30);
+
+        let game = go_down(one_defender_at(Ruleset {
+            dealer_soups: DealerSoups::Consolation,
+            ..rules
+        }));
+        // One trick worth of diamonds on the declarer
+        assert_eq!(game.score[3].soups_on(0), 6);
+        assert_eq!(game.score[3].total_soups(), 6);
+        assert_eq!(game.score[3].bulls(), 30);
+        let entry = game.ledger.entries().last().unwrap();
+        assert_eq!(entry.soups[3][0], 6);
     }
 }
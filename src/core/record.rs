@@ -0,0 +1,1022 @@
+use std::fmt::{self, Write};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    actions::{CardChoice, GameAction, GameActionKind},
+    choosing::ContreLevel,
+    game::{CardsInPlay, GameError, GameState, new_game, pg_json},
+    notation::NotationError,
+    ruleset::{AllPass, DealerSoups, MAX_SEATS, Ruleset, UndoPolicy},
+    types::{GameContractData, GameContractKind},
+};
+
+/*
+   Portable game record, loosely modelled on chess PGN.
+
+   [Player1 "ana"]
+   [Player2 "bob"]
+   [Player3 "cira"]
+   [Ruleset "Standard"]
+   [Bula "60"]
+   [Refe "2"]
+   [First "1"]
+
+   Hand 1
+   Deal: ♠AK ♦- ♥AKJ987 ♣Q8 / ♠J87 ♦AQ8 ♥Q ♣KJ7 / ♠Q109 ♦KJ97 ♥10 ♣A10 / 10♦ 9♣
+   Auction: 1:Bid 2:Bid 3:Pass 1:Bid 2:Pass
+   Talon: 1:Exchange(+10♦,+9♣,-8♣,-Q♣)
+   Contract: 1:Choose(Hearts) {1 Hearts}
+   Responses: 2:Accept 3:Reject
+   Tricks: 1. 2:J♠ 1:A♠ 2. 1:A♥ 2:Q♥ ...
+   Score: 1:-8/+0 2:+0/+8 3:+0/+0
+
+   Seats are numbered from 1. Everything inside `{}` and the trick numbers are annotations
+   derived while exporting and are skipped on import. The action lines are replayed in order
+   through `GameState::apply`, and the `Score` line, when present, is checked against the
+   replayed result.
+
+   Tables of four add `[Seats "4"]` and, when the dealer scores, `[DealerSoups "Consolation"]`.
+   The dealer gets no cards, so the deal lists the other three hands going round from the
+   dealer's left, and the dealer's seat never appears in the action lines.
+
+   Games that play out hands everyone passed add `[AllPass "PlayOut"]`. The talon card turned up
+   for each of the first two tricks of such a hand is annotated after the trick number.
+   `[OpenPlay "true"]` allows defenders of betl and sans to lay their cards open, which shows up
+   as `2:Open` among the tricks.
+
+   `[HonorMode "true"]` lets any card in hand be played. A called revoke shows up as `1:Revoke`,
+   and every player accepting the finished hand as `1:Confirm`, both kept with the trick they
+   follow.
+
+   A claim of the declarer reads `1:Claim(3)`, for three of the tricks left, and a concession
+   `2:Concede`, each followed by the others' `AcceptClaim` or `RejectClaim`. `[Ranked "true"]`
+   games only allow claims a double dummy search confirms.
+
+   `[Undo "Never"]` or `[Undo "Always"]` sets when players may take an action back; by default
+   they may unless the game is ranked. Actions taken back are dropped from the record.
+*/
+
+pub const STANDARD_RULESET: &str = "Standard";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameRecord {
+    pub header: RecordHeader,
+    pub hands: Vec<HandRecord>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordHeader {
+    #[serde(default)]
+    pub players: Vec<String>,
+    pub ruleset: String,
+    pub bula: u32,
+    pub refas: usize,
+    pub first: usize,
+    #[serde(default)]
+    pub rules: Ruleset,
+}
+
+impl RecordHeader {
+    /// Seat dealing each hand in turn, starting from the first one.
+    fn dealers(&self) -> impl Iterator<Item = usize> + '_ {
+        std::iter::successors(Some(self.rules.dealer_before(self.first)), |&dealer| {
+            Some(self.rules.next_dealer(dealer))
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HandRecord {
+    pub deal: CardsInPlay,
+    pub actions: Vec<GameAction>,
+}
+
+impl HandRecord {
+    fn new(deal: CardsInPlay) -> Self {
+        Self {
+            deal,
+            actions: Vec::new(),
+        }
+    }
+}
+
+/// Bula and soups change of one seat over a hand.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct ScoreDelta {
+    bulls: i64,
+    soups: i64,
+}
+
+/// What happened in a hand, with players as table seats.
+#[derive(Debug, Default)]
+struct HandSummary {
+    dealer: usize,
+    all_pass: bool,
+    contract: Option<(usize, GameContractData, ContreLevel)>,
+    /// Moves of each trick by seat, cards and hands laid open alike.
+    tricks: Vec<Vec<(usize, GameActionKind)>>,
+    score: Option<Vec<ScoreDelta>>,
+}
+
+impl HandSummary {
+    /// Adds the moves left at the end of the hand. Calls made after the last card stay with the
+    /// trick they follow.
+    fn push_last_trick(&mut self, trick: Vec<(usize, GameActionKind)>) {
+        let has_cards = trick
+            .iter()
+            .any(|(_, kind)| matches!(kind, GameActionKind::PlayCard(_)));
+
+        match self.tricks.last_mut() {
+            Some(last) if !has_cards => last.extend(trick),
+            _ => self.tricks.push(trick),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RecordError {
+    #[error("line {line}: {message}")]
+    Syntax { line: usize, message: String },
+
+    #[error("line {line}: {source}")]
+    Notation { line: usize, source: NotationError },
+
+    #[error("missing tag `{0}`")]
+    MissingTag(&'static str),
+
+    #[error("unsupported ruleset `{0}`")]
+    UnsupportedRuleset(String),
+
+    #[error("hand {0} has no deal")]
+    MissingDeal(usize),
+
+    #[error("hand {hand}, action {action}: {source}")]
+    Game {
+        hand: usize,
+        action: usize,
+        source: GameError,
+    },
+
+    #[error("hand {0} is not finished but another hand follows it")]
+    HandNotFinished(usize),
+
+    #[error("hand {0} can't be dealt before the game comes to a new hand")]
+    NotAtHandStart(usize),
+
+    #[error("hand {0} has actions after it ended")]
+    ActionsAfterHandEnd(usize),
+
+    #[error("hand {0} score does not match the replayed result")]
+    ScoreMismatch(usize),
+
+    #[error("seat {} is not at the table", .0 + 1)]
+    InvalidSeat(usize),
+
+    #[error("hand {hand}: seat {} deals and holds no cards", .seat + 1)]
+    SeatNotInHand { hand: usize, seat: usize },
+}
+
+impl GameRecord {
+    /// Starts a record for a freshly created game.
+    pub fn new(header: RecordHeader, state: &GameState) -> Self {
+        Self {
+            header,
+            hands: vec![HandRecord::new(*state.cards())],
+        }
+    }
+
+    /// Appends an action that was just applied, `state` being the result of applying it.
+    pub fn push(&mut self, action: GameAction, state: &GameState) {
+        self.hands
+            .last_mut()
+            .expect("Record always has a hand")
+            .actions
+            .push(action);
+
+        if state.is_hand_start() {
+            self.hands.push(HandRecord::new(*state.cards()));
+        }
+    }
+
+    /// Drops the last action of table seat `seat` and everything after it, returning whether
+    /// there was one. Only the hand in progress is searched: taking back the end of a hand
+    /// would throw its successor's deal away and let the table deal again. `replay` gives the
+    /// state to carry on from.
+    pub fn take_back(&mut self, seat: usize) -> bool {
+        let hand = self.hands.len() - 1;
+        let dealer = self
+            .header
+            .dealers()
+            .nth(hand)
+            .expect("Dealers never run out");
+        let Some(position) = self.header.rules.position_of(dealer, seat) else {
+            return false;
+        };
+        let Some(index) = self.hands[hand]
+            .actions
+            .iter()
+            .rposition(|action| action.player == position)
+        else {
+            return false;
+        };
+
+        self.hands[hand].actions.truncate(index);
+        true
+    }
+
+    /// Rebuilds the game state by replaying every recorded action.
+    pub fn replay(&self) -> Result<GameState, RecordError> {
+        self.replay_summarized().map(|(state, _)| state)
+    }
+
+    /// Writes the record in its text form.
+    pub fn export(&self) -> Result<String, RecordError> {
+        let (_, summaries) = self.replay_summarized()?;
+
+        let mut out = String::new();
+        self.write_header(&mut out).expect("Writing to a String");
+        for (i, (hand, summary)) in self.hands.iter().zip(&summaries).enumerate() {
+            out.push('\n');
+            write_hand(&mut out, i + 1, hand, summary, &self.header.rules)
+                .expect("Writing to a String");
+        }
+
+        Ok(out)
+    }
+
+    /// Parses a record from its text form and replays it, checking recorded scores on the way.
+    pub fn import(text: &str) -> Result<(GameRecord, GameState), RecordError> {
+        let (record, scores) = parse(text)?;
+        let (state, summaries) = record.replay_summarized()?;
+
+        for (i, (expected, summary)) in scores.iter().zip(&summaries).enumerate() {
+            if expected.is_some() && *expected != summary.score {
+                return Err(RecordError::ScoreMismatch(i + 1));
+            }
+        }
+
+        Ok((record, state))
+    }
+
+    fn replay_summarized(&self) -> Result<(GameState, Vec<HandSummary>), RecordError> {
+        let rules = self.header.rules;
+        let mut state = new_game(
+            self.header.first,
+            self.header.bula,
+            self.header.refas,
+            rules,
+        );
+        let mut summaries = Vec::with_capacity(self.hands.len());
+
+        for (i, hand) in self.hands.iter().enumerate() {
+            let hand_number = i + 1;
+            state = redeal(state, hand.deal).ok_or(RecordError::NotAtHandStart(hand_number))?;
+
+            let dealer = state.dealer();
+            let score_before = state.score().to_vec();
+            let mut summary = HandSummary {
+                dealer,
+                ..Default::default()
+            };
+            let mut trick = Vec::new();
+            let mut tricks_taken = 0;
+
+            for (j, action) in hand.actions.iter().enumerate() {
+                if Section::of(&action.kind) == Section::Tricks {
+                    trick.push((rules.seat_of(dealer, action.player), action.kind.clone()));
+                }
+
+                state = state
+                    .apply(action.clone())
+                    .map_err(|source| RecordError::Game {
+                        hand: hand_number,
+                        action: j + 1,
+                        source,
+                    })?;
+
+                let tricks_played = match &state {
+                    GameState::Playing(game) => {
+                        summary.contract.get_or_insert((
+                            game.seat_of(game.state.declarer()),
+                            game.state.contract(),
+                            game.state.contre_level(),
+                        ));
+                        Some(game.state.tricks().iter().sum())
+                    }
+                    GameState::AllPass(game) => {
+                        summary.all_pass = true;
+                        Some(game.state.tricks_played())
+                    }
+                    _ => None,
+                };
+
+                if let Some(total) = tricks_played
+                    && total > tricks_taken
+                {
+                    tricks_taken = total;
+                    summary.tricks.push(std::mem::take(&mut trick));
+                }
+
+                if state.is_hand_start() {
+                    if j + 1 != hand.actions.len() {
+                        return Err(RecordError::ActionsAfterHandEnd(hand_number));
+                    }
+
+                    if !trick.is_empty() {
+                        summary.push_last_trick(std::mem::take(&mut trick));
+                    }
+
+                    let score_after = state.score();
+                    summary.score = Some(
+                        (0..score_after.len())
+                            .map(|p| ScoreDelta {
+                                bulls: (score_after[p].bulls() - score_before[p].bulls()) as i64,
+                                soups: score_after[p].total_soups() as i64
+                                    - score_before[p].total_soups() as i64,
+                            })
+                            .collect(),
+                    );
+                }
+            }
+
+            if i + 1 != self.hands.len() && summary.score.is_none() {
+                return Err(RecordError::HandNotFinished(hand_number));
+            }
+
+            if !trick.is_empty() {
+                summary.push_last_trick(trick);
+            }
+
+            summaries.push(summary);
+        }
+
+        Ok((state, summaries))
+    }
+
+    fn write_header(&self, out: &mut String) -> fmt::Result {
+        for (i, player) in self.header.players.iter().enumerate() {
+            writeln!(out, "[Player{} \"{}\"]", i + 1, player)?;
+        }
+        writeln!(out, "[Ruleset \"{}\"]", self.header.ruleset)?;
+        writeln!(out, "[Bula \"{}\"]", self.header.bula)?;
+        writeln!(out, "[Refe \"{}\"]", self.header.refas)?;
+        writeln!(out, "[First \"{}\"]", self.header.first + 1)?;
+
+        let rules = &self.header.rules;
+        if rules.dealer_sits_out() {
+            writeln!(out, "[Seats \"{}\"]", rules.seats)?;
+            if rules.dealer_soups != DealerSoups::None {
+                writeln!(out, "[DealerSoups \"{:?}\"]", rules.dealer_soups)?;
+            }
+        }
+        if rules.all_pass != AllPass::Redeal {
+            writeln!(out, "[AllPass \"{:?}\"]", rules.all_pass)?;
+        }
+        if rules.open_play {
+            writeln!(out, "[OpenPlay \"true\"]")?;
+        }
+        if rules.honor_mode {
+            writeln!(out, "[HonorMode \"true\"]")?;
+        }
+        if rules.ranked {
+            writeln!(out, "[Ranked \"true\"]")?;
+        }
+        if rules.undo != UndoPolicy::default() {
+            writeln!(out, "[Undo \"{:?}\"]", rules.undo)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Puts a recorded deal in place of the random one the engine dealt for the new hand.
+fn redeal(state: GameState, deal: CardsInPlay) -> Option<GameState> {
+    match state {
+        GameState::Bidding(mut game) if game.is_hand_start() => {
+            game.cards = deal;
+            Some(GameState::Bidding(game))
+        }
+        _ => None,
+    }
+}
+
+#[derive(PartialEq)]
+enum Section {
+    Auction,
+    Talon,
+    Contract,
+    Responses,
+    Tricks,
+}
+
+impl Section {
+    const ALL: [Section; 5] = [
+        Section::Auction,
+        Section::Talon,
+        Section::Contract,
+        Section::Responses,
+        Section::Tricks,
+    ];
+
+    fn of(kind: &GameActionKind) -> Self {
+        use GameActionKind::*;
+        match kind {
+            Bid | PassBid | ClaimNoBid | ChooseNoBidContract(_) => Section::Auction,
+            ChooseCards(_) => Section::Talon,
+            ChooseContract(_) => Section::Contract,
+            AcceptContract | RejectContract | CallForHelp | DeclareContre | PassHelpContre => {
+                Section::Responses
+            }
+            PlayCard(_)
+            | LayOpen
+            | CallRevoke
+            | ConfirmHand
+            | Claim { .. }
+            | Concede
+            | AcceptClaim
+            | RejectClaim => Section::Tricks,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Section::Auction => "Auction",
+            Section::Talon => "Talon",
+            Section::Contract => "Contract",
+            Section::Responses => "Responses",
+            Section::Tricks => "Tricks",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Section::ALL.into_iter().find(|s| s.name() == name)
+    }
+}
+
+fn write_hand(
+    out: &mut String,
+    number: usize,
+    hand: &HandRecord,
+    summary: &HandSummary,
+    rules: &Ruleset,
+) -> fmt::Result {
+    writeln!(out, "Hand {}", number)?;
+    writeln!(out, "Deal: {}", hand.deal)?;
+
+    for section in Section::ALL {
+        if section == Section::Tricks {
+            continue;
+        }
+
+        let actions: Vec<&GameAction> = hand
+            .actions
+            .iter()
+            .filter(|a| Section::of(&a.kind) == section)
+            .collect();
+
+        let annotate = section == Section::Contract && summary.contract.is_some();
+        if actions.is_empty() && !annotate {
+            continue;
+        }
+
+        write!(out, "{}:", section.name())?;
+        for action in actions {
+            let seat = rules.seat_of(summary.dealer, action.player);
+            write!(out, " {}", ActionToken(seat, &action.kind))?;
+        }
+
+        if let (true, Some((declarer, contract, contre))) = (annotate, summary.contract) {
+            write!(out, " {{{} {}", declarer + 1, contract.value)?;
+            if let GameContractKind::NoBid = contract.kind {
+                write!(out, " NoBid")?;
+            }
+            if contre != ContreLevel::NoContre {
+                write!(out, " {:?}", contre)?;
+            }
+            write!(out, "}}")?;
+        }
+        writeln!(out)?;
+    }
+
+    if !summary.tricks.is_empty() {
+        write!(out, "Tricks:")?;
+        for (i, trick) in summary.tricks.iter().enumerate() {
+            write!(out, " {}.", i + 1)?;
+            if let Some(talon_card) = hand.deal.hidden.get(i).filter(|_| summary.all_pass) {
+                write!(out, " {{{}}}", talon_card)?;
+            }
+            for (seat, kind) in trick {
+                write!(out, " {}", ActionToken(*seat, kind))?;
+            }
+        }
+        writeln!(out)?;
+    }
+
+    if let Some(score) = &summary.score {
+        write!(out, "Score:")?;
+        for (i, delta) in score.iter().enumerate() {
+            write!(out, " {}:{:+}/{:+}", i + 1, delta.bulls, delta.soups)?;
+        }
+        writeln!(out)?;
+    }
+
+    Ok(())
+}
+
+/// Displays an action of a table seat as `<seat>:<move>`, e.g. `2:Bid`, `1:Choose(Hearts)`,
+/// `3:A♠`.
+struct ActionToken<'a>(usize, &'a GameActionKind);
+
+impl fmt::Display for ActionToken<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use GameActionKind::*;
+
+        write!(f, "{}:", self.0 + 1)?;
+        match self.1 {
+            Bid => f.write_str("Bid"),
+            PassBid => f.write_str("Pass"),
+            ClaimNoBid => f.write_str("NoBid"),
+            ChooseNoBidContract(contract) => write!(f, "NoBid({})", contract),
+            ChooseCards(choice) => {
+                let take = choice.take_cards.iter().map(|c| format!("+{}", c));
+                let discard = choice.discard_cards.iter().map(|c| format!("-{}", c));
+                let cards: Vec<String> = take.chain(discard).collect();
+                write!(f, "Exchange({})", cards.join(","))
+            }
+            ChooseContract(contract) => write!(f, "Choose({})", contract),
+            AcceptContract => f.write_str("Accept"),
+            RejectContract => f.write_str("Reject"),
+            CallForHelp => f.write_str("Help"),
+            DeclareContre => f.write_str("Contre"),
+            PassHelpContre => f.write_str("PassContre"),
+            PlayCard(card) => write!(f, "{}", card),
+            LayOpen => f.write_str("Open"),
+            CallRevoke => f.write_str("Revoke"),
+            ConfirmHand => f.write_str("Confirm"),
+            Claim { tricks } => write!(f, "Claim({})", tricks),
+            Concede => f.write_str("Concede"),
+            AcceptClaim => f.write_str("AcceptClaim"),
+            RejectClaim => f.write_str("RejectClaim"),
+        }
+    }
+}
+
+fn parse_action(token: &str, line: usize) -> Result<GameAction, RecordError> {
+    let syntax = |message: &str| RecordError::Syntax {
+        line,
+        message: format!("{} in `{}`", message, token),
+    };
+    let notation = |source| RecordError::Notation { line, source };
+
+    let (seat, mv) = token
+        .split_once(':')
+        .ok_or_else(|| syntax("missing seat"))?;
+    let player = parse_seat(seat).ok_or_else(|| syntax("invalid seat"))?;
+
+    let (name, args) = match mv.split_once('(') {
+        Some((name, rest)) => {
+            let args = rest
+                .strip_suffix(')')
+                .ok_or_else(|| syntax("unclosed parenthesis"))?;
+            (name, Some(args))
+        }
+        None => (mv, None),
+    };
+
+    use GameActionKind::*;
+    let kind = match (name, args) {
+        ("Bid", None) => Bid,
+        ("Pass", None) => PassBid,
+        ("NoBid", None) => ClaimNoBid,
+        ("NoBid", Some(contract)) => ChooseNoBidContract(contract.parse().map_err(notation)?),
+        ("Exchange", Some(cards)) => ChooseCards(parse_exchange(cards).map_err(notation)?),
+        ("Choose", Some(contract)) => ChooseContract(contract.parse().map_err(notation)?),
+        ("Accept", None) => AcceptContract,
+        ("Reject", None) => RejectContract,
+        ("Help", None) => CallForHelp,
+        ("Contre", None) => DeclareContre,
+        ("PassContre", None) => PassHelpContre,
+        ("Open", None) => LayOpen,
+        ("Revoke", None) => CallRevoke,
+        ("Confirm", None) => ConfirmHand,
+        ("Claim", Some(tricks)) => Claim {
+            tricks: tricks.parse().map_err(|_| syntax("invalid trick count"))?,
+        },
+        ("Concede", None) => Concede,
+        ("AcceptClaim", None) => AcceptClaim,
+        ("RejectClaim", None) => RejectClaim,
+        (card, None) => PlayCard(card.parse().map_err(notation)?),
+        _ => return Err(syntax("unknown move")),
+    };
+
+    Ok(GameAction::new(player, kind))
+}
+
+fn parse_exchange(cards: &str) -> Result<CardChoice, NotationError> {
+    let mut choice = CardChoice {
+        take_cards: Vec::new(),
+        discard_cards: Vec::new(),
+    };
+
+    for token in cards.split(',').filter(|t| !t.is_empty()) {
+        if let Some(card) = token.strip_prefix('+') {
+            choice.take_cards.push(card.parse()?);
+        } else if let Some(card) = token.strip_prefix('-') {
+            choice.discard_cards.push(card.parse()?);
+        } else {
+            return Err(NotationError::InvalidCard(token.into()));
+        }
+    }
+
+    Ok(choice)
+}
+
+fn parse_seat(seat: &str) -> Option<usize> {
+    match seat.parse::<usize>() {
+        Ok(n @ 1..=MAX_SEATS) => Some(n - 1),
+        _ => None,
+    }
+}
+
+fn parse_score(rest: &str, line: usize) -> Result<Vec<ScoreDelta>, RecordError> {
+    let syntax = |token: &str| RecordError::Syntax {
+        line,
+        message: format!("invalid score `{}`", token),
+    };
+
+    let mut score = [ScoreDelta::default(); MAX_SEATS];
+    let mut seen = [false; MAX_SEATS];
+    for token in rest.split_whitespace() {
+        let (seat, delta) = token.split_once(':').ok_or_else(|| syntax(token))?;
+        let (bulls, soups) = delta.split_once('/').ok_or_else(|| syntax(token))?;
+        let seat = parse_seat(seat).ok_or_else(|| syntax(token))?;
+
+        score[seat] = ScoreDelta {
+            bulls: bulls.parse().map_err(|_| syntax(token))?,
+            soups: soups.parse().map_err(|_| syntax(token))?,
+        };
+        seen[seat] = true;
+    }
+
+    // Seats have to be listed from the first one without gaps.
+    let seats = seen.iter().take_while(|&&x| x).count();
+    if seen[seats..].iter().any(|&x| x) || seats == 0 {
+        return Err(syntax(rest));
+    }
+
+    Ok(score[..seats].to_vec())
+}
+
+fn parse_tag(line: &str) -> Option<(&str, &str)> {
+    let inner = line.strip_prefix('[')?.strip_suffix(']')?;
+    let (name, value) = inner.split_once(' ')?;
+    let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+
+    Some((name, value))
+}
+
+/// Drops `{...}` annotations from a line.
+fn strip_annotations(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut depth = 0;
+    for c in line.chars() {
+        match c {
+            '{' => depth += 1,
+            '}' if depth > 0 => depth -= 1,
+            _ if depth == 0 => out.push(c),
+            _ => {}
+        }
+    }
+
+    out
+}
+
+struct ParsedHand {
+    deal: Option<CardsInPlay>,
+    actions: Vec<GameAction>,
+    score: Option<Vec<ScoreDelta>>,
+}
+
+type ParsedRecord = (GameRecord, Vec<Option<Vec<ScoreDelta>>>);
+
+fn parse(text: &str) -> Result<ParsedRecord, RecordError> {
+    let mut players = Vec::new();
+    let mut ruleset = None;
+    let mut bula = None;
+    let mut refas = None;
+    let mut first = None;
+    let mut rules = Ruleset::default();
+    let mut hands: Vec<ParsedHand> = Vec::new();
+
+    for (i, raw) in text.lines().enumerate() {
+        let line = i + 1;
+        let syntax = |message: String| RecordError::Syntax { line, message };
+
+        let raw = raw.trim();
+        if raw.is_empty() {
+            continue;
+        }
+
+        if raw.starts_with('[') {
+            let (name, value) = parse_tag(raw).ok_or_else(|| syntax("invalid tag".into()))?;
+            let number = || {
+                value
+                    .parse::<usize>()
+                    .map_err(|_| syntax(format!("invalid value for `{}`", name)))
+            };
+
+            match name {
+                "Ruleset" => ruleset = Some(value.to_string()),
+                "Bula" => bula = Some(number()? as u32),
+                "Refe" => refas = Some(number()?),
+                "First" => {
+                    first = Some(parse_seat(value).ok_or_else(|| syntax("invalid seat".into()))?)
+                }
+                "Seats" => {
+                    rules.seats = number()?;
+                    if !rules.is_valid() {
+                        return Err(syntax(format!("unsupported number of seats `{}`", value)));
+                    }
+                }
+                "OpenPlay" => {
+                    rules.open_play = value
+                        .parse()
+                        .map_err(|_| syntax(format!("invalid value for `{}`", name)))?
+                }
+                "HonorMode" => {
+                    rules.honor_mode = value
+                        .parse()
+                        .map_err(|_| syntax(format!("invalid value for `{}`", name)))?
+                }
+                "Ranked" => {
+                    rules.ranked = value
+                        .parse()
+                        .map_err(|_| syntax(format!("invalid value for `{}`", name)))?
+                }
+                "AllPass" => {
+                    rules.all_pass = match value {
+                        "Redeal" => AllPass::Redeal,
+                        "PlayOut" => AllPass::PlayOut,
+                        _ => return Err(syntax(format!("invalid value for `{}`", name))),
+                    }
+                }
+                "Undo" => {
+                    rules.undo = match value {
+                        "Never" => UndoPolicy::Never,
+                        "CasualOnly" => UndoPolicy::CasualOnly,
+                        "Always" => UndoPolicy::Always,
+                        _ => return Err(syntax(format!("invalid value for `{}`", name))),
+                    }
+                }
+                "DealerSoups" => {
+                    rules.dealer_soups = match value {
+                        "None" => DealerSoups::None,
+                        "Consolation" => DealerSoups::Consolation,
+                        _ => return Err(syntax(format!("invalid value for `{}`", name))),
+                    }
+                }
+                _ if name.starts_with("Player") => players.push(value.to_string()),
+                _ => {}
+            }
+            continue;
+        }
+
+        if raw.starts_with("Hand ") {
+            hands.push(ParsedHand {
+                deal: None,
+                actions: Vec::new(),
+                score: None,
+            });
+            continue;
+        }
+
+        let (key, rest) = raw
+            .split_once(':')
+            .ok_or_else(|| syntax(format!("unexpected line `{}`", raw)))?;
+        let hand = hands
+            .last_mut()
+            .ok_or_else(|| syntax("expected `Hand` before hand data".into()))?;
+
+        match key {
+            "Deal" => {
+                let deal = rest
+                    .trim()
+                    .parse()
+                    .map_err(|source| RecordError::Notation { line, source })?;
+                hand.deal = Some(deal);
+            }
+            "Score" => hand.score = Some(parse_score(rest, line)?),
+            _ if Section::from_name(key).is_some() => {
+                let rest = strip_annotations(rest);
+                for token in rest.split_whitespace() {
+                    let is_trick_number =
+                        token.ends_with('.') && token[..token.len() - 1].parse::<usize>().is_ok();
+                    if !is_trick_number {
+                        hand.actions.push(parse_action(token, line)?);
+                    }
+                }
+            }
+            _ => return Err(syntax(format!("unknown section `{}`", key))),
+        }
+    }
+
+    let ruleset = ruleset.ok_or(RecordError::MissingTag("Ruleset"))?;
+    if ruleset != STANDARD_RULESET {
+        return Err(RecordError::UnsupportedRuleset(ruleset));
+    }
+
+    let header = RecordHeader {
+        players,
+        ruleset,
+        bula: bula.ok_or(RecordError::MissingTag("Bula"))?,
+        refas: refas.ok_or(RecordError::MissingTag("Refe"))?,
+        first: first.ok_or(RecordError::MissingTag("First"))?,
+        rules,
+    };
+
+    if header.first >= rules.seats {
+        return Err(RecordError::InvalidSeat(header.first));
+    }
+
+    if hands.is_empty() {
+        return Err(RecordError::MissingDeal(1));
+    }
+
+    let mut record = GameRecord {
+        header,
+        hands: Vec::with_capacity(hands.len()),
+    };
+    let mut scores = Vec::with_capacity(hands.len());
+    let dealers: Vec<usize> = record.header.dealers().take(hands.len()).collect();
+    for (i, (hand, dealer)) in hands.into_iter().zip(dealers).enumerate() {
+        // Action lines name table seats, the engine works with hand positions.
+        let actions = hand
+            .actions
+            .into_iter()
+            .map(|action| {
+                let seat = action.player;
+                rules
+                    .position_of(dealer, seat)
+                    .map(|position| GameAction::new(position, action.kind))
+                    .ok_or(if seat < rules.seats {
+                        RecordError::SeatNotInHand { hand: i + 1, seat }
+                    } else {
+                        RecordError::InvalidSeat(seat)
+                    })
+            })
+            .collect::<Result<_, _>>()?;
+
+        record.hands.push(HandRecord {
+            deal: hand.deal.ok_or(RecordError::MissingDeal(i + 1))?,
+            actions,
+        });
+        scores.push(hand.score);
+    }
+
+    Ok((record, scores))
+}
+
+pg_json!(GameRecord);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::types::GameContract;
+
+    const DEAL: &str = "♠AKQJ10 ♦AK ♥A ♣AK / ♠987 ♦QJ10 ♥KQJ ♣Q / ♠- ♦987 ♥1098 ♣J1098 / 7♥ 7♣";
+
+    fn turn(state: &GameState) -> usize {
+        match state {
+            GameState::Bidding(game) => game.turn,
+            GameState::ChoosingCards(game) => game.turn,
+            GameState::ChoosingContract(game) => game.turn,
+            GameState::RespondingToContract(game) => game.turn,
+            GameState::HelpOrContreToContract(game) => game.turn,
+            GameState::Playing(game) => game.turn,
+            _ => panic!("Not a state these tests reach"),
+        }
+    }
+
+    /// Has `player` take `kind` and records it.
+    fn act(
+        record: &mut GameRecord,
+        state: GameState,
+        player: usize,
+        kind: GameActionKind,
+    ) -> GameState {
+        let action = GameAction::new(player, kind);
+        let state = state.apply(action.clone()).unwrap();
+        record.push(action, &state);
+        state
+    }
+
+    /// A game of one hand on `DEAL`: the first player declares diamonds and claims eight
+    /// tricks, which the defender and the player who passed both accept.
+    fn played() -> (GameRecord, GameState) {
+        let header = RecordHeader {
+            players: vec!["ana".into(), "bob".into(), "cira".into()],
+            ruleset: STANDARD_RULESET.into(),
+            bula: 30,
+            refas: 0,
+            first: 0,
+            rules: Ruleset::default(),
+        };
+        let state = match new_game(0, 30, 0, header.rules) {
+            GameState::Bidding(mut game) => {
+                game.cards = DEAL.parse().unwrap();
+                GameState::Bidding(game)
+            }
+            _ => unreachable!(),
+        };
+        let mut record = GameRecord::new(header, &state);
+
+        let exchange = CardChoice {
+            take_cards: vec!["7♥".parse().unwrap(), "7♣".parse().unwrap()],
+            discard_cards: vec!["A♦".parse().unwrap(), "K♦".parse().unwrap()],
+        };
+        let mut state = state;
+        for kind in [
+            GameActionKind::Bid,
+            GameActionKind::PassBid,
+            GameActionKind::PassBid,
+            GameActionKind::ChooseCards(exchange),
+            GameActionKind::ChooseContract(GameContract::Diamonds),
+            GameActionKind::RejectContract,
+            GameActionKind::AcceptContract,
+        ] {
+            let player = turn(&state);
+            state = act(&mut record, state, player, kind);
+        }
+        while let GameState::HelpOrContreToContract(_) = state {
+            let player = turn(&state);
+            state = act(&mut record, state, player, GameActionKind::PassHelpContre);
+        }
+        for (player, kind) in [
+            (0, GameActionKind::Claim { tricks: 8 }),
+            (2, GameActionKind::AcceptClaim),
+            (1, GameActionKind::AcceptClaim),
+        ] {
+            state = act(&mut record, state, player, kind);
+        }
+
+        (record, state)
+    }
+    #[test]
+    fn exported_records_import_the_same() {
+        let (record, state) = played();
+        let text = record.export().unwrap();
+
+        let (imported, replayed) = GameRecord::import(&text).unwrap();
+        assert_eq!(imported.export().unwrap(), text);
+        assert_eq!(imported.header.players, record.header.players);
+        for (replayed, played) in replayed.score().iter().zip(state.score()) {
+            assert_eq!(replayed.bulls(), played.bulls());
+            assert_eq!(replayed.total_soups(), played.total_soups());
+        }
+    }
+
+    #[test]
+    fn malformed_records_are_refused() {
+        let (record, _) = played();
+        let text = record.export().unwrap();
+
+        let import = |from: &str, to: &str| GameRecord::import(&text.replace(from, to));
+        assert!(matches!(
+            import("1:Bid", "1:Bet"),
+            Err(RecordError::Notation { line: 11, .. })
+        ));
+        assert!(matches!(
+            import("3:+0/+12", "3:+0/+10"),
+            Err(RecordError::ScoreMismatch(1))
+        ));
+        assert!(matches!(
+            import("-A♦,-K♦", "-A♦,-Q♦"),
+            Err(RecordError::Game {
+                hand: 1,
+                action: 4,
+                ..
+            })
+        ));
+        assert!(matches!(
+            import("[Bula \"30\"]\n", ""),
+            Err(RecordError::MissingTag(_))
+        ));
+    }
+
+    #[test]
+    fn replay_scores_the_hand() {
+        let (record, _) = played();
+        let state = record.replay().unwrap();
+        let score = state.score();
+
+        // Diamonds made: the declarer's bula goes down by 6, and the defender who followed
+        // writes 6 soups for each of the two tricks left to them
+        assert_eq!(score[0].bulls(), 24);
+        assert_eq!(score[1].bulls(), 30);
+        assert_eq!(score[2].bulls(), 30);
+        assert_eq!(score[2].soups_on(0), 12);
+        assert_eq!(score[1].total_soups(), 0);
+        assert!(state.is_hand_start());
+    }
+}
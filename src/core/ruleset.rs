@@ -0,0 +1,175 @@
+use serde::{Deserialize, Serialize};
+
+/// Players holding cards in a hand. Hand positions run from 0 to 2 whatever the table size.
+pub const HAND_PLAYERS: usize = 3;
+
+/// Most people a table can seat.
+pub const MAX_SEATS: usize = 4;
+
+/// Rules a game was created with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Ruleset {
+    /// People at the table, 3 or 4. With four the dealer gets no cards and sits the hand out,
+    /// but keeps their place on the score sheet.
+    pub seats: usize,
+    /// What the dealer sitting out writes for the hand.
+    pub dealer_soups: DealerSoups,
+    /// What happens when everyone passes.
+    pub all_pass: AllPass,
+    /// Betl and sans are played open: the betl declarer's hand is shown after the first
+    /// trick, and when both opponents defend either of them may lay their cards open.
+    pub open_play: bool,
+    /// Any card in hand may be played, as over the board. Legality is checked afterwards: an
+    /// opponent who calls a revoke ends the hand, scored as lost by the side that revoked.
+    /// Once the last trick is in everyone confirms the hand before it is scored.
+    pub honor_mode: bool,
+    /// Ranked games check every claim with a double dummy search and refuse the ones that
+    /// aren't sure to come true.
+    pub ranked: bool,
+    /// When a player may take back their last action, with the other players' consent.
+    pub undo: UndoPolicy,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum DealerSoups {
+    /// The dealer's score doesn't change.
+    #[default]
+    None,
+    /// When the declarer goes down the dealer writes one trick worth of soups on them.
+    Consolation,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum AllPass {
+    /// The cards are thrown in and the next dealer deals.
+    #[default]
+    Redeal,
+    /// The hand is played out without trumps and every trick taken costs bula.
+    PlayOut,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum UndoPolicy {
+    Never,
+    /// Everywhere but ranked games.
+    #[default]
+    CasualOnly,
+    Always,
+}
+
+impl Default for Ruleset {
+    fn default() -> Self {
+        Self {
+            seats: HAND_PLAYERS,
+            dealer_soups: DealerSoups::None,
+            all_pass: AllPass::Redeal,
+            open_play: false,
+            honor_mode: false,
+            ranked: false,
+            undo: UndoPolicy::CasualOnly,
+        }
+    }
+}
+
+impl Ruleset {
+    pub fn is_valid(&self) -> bool {
+        (HAND_PLAYERS..=MAX_SEATS).contains(&self.seats)
+    }
+
+    pub fn allows_undo(&self) -> bool {
+        match self.undo {
+            UndoPolicy::Never => false,
+            UndoPolicy::CasualOnly => !self.ranked,
+            UndoPolicy::Always => true,
+        }
+    }
+
+    pub fn dealer_sits_out(&self) -> bool {
+        self.seats > HAND_PLAYERS
+    }
+
+    /// Seat that deals after `dealer`, passing the deal to the left.
+    pub fn next_dealer(&self, dealer: usize) -> usize {
+        (dealer + 1) % self.seats
+    }
+
+    /// Seat that deals before the player in `first` seat opens the bidding.
+    pub fn dealer_before(&self, first: usize) -> usize {
+        (first + self.seats - 1) % self.seats
+    }
+
+    /// Table seat playing hand position `position` while `dealer` deals.
+    ///
+    /// Positions follow the seats around the table, skipping the dealer when they sit out.
+    pub fn seat_of(&self, dealer: usize, position: usize) -> usize {
+        if self.dealer_sits_out() && position >= dealer {
+            position + 1
+        } else {
+            position
+        }
+    }
+
+    /// Hand position of a table seat while `dealer` deals, `None` when the seat holds no cards.
+    pub fn position_of(&self, dealer: usize, seat: usize) -> Option<usize> {
+        if seat >= self.seats {
+            return None;
+        }
+
+        if !self.dealer_sits_out() || seat < dealer {
+            Some(seat)
+        } else if seat == dealer {
+            None
+        } else {
+            Some(seat - 1)
+        }
+    }
+
+    /// Position of the player on the dealer's left, who opens the bidding.
+    pub fn first_position(&self, dealer: usize) -> usize {
+        self.position_of(dealer, self.next_dealer(dealer))
+            .expect("The dealer's neighbour always plays")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_seat_plays_at_a_table_of_three() {
+        let rules = Ruleset::default();
+        for dealer in 0..3 {
+            for seat in 0..3 {
+                assert_eq!(rules.position_of(dealer, seat), Some(seat));
+                assert_eq!(rules.seat_of(dealer, seat), seat);
+            }
+            assert_eq!(rules.first_position(dealer), rules.next_dealer(dealer));
+        }
+        assert_eq!(rules.dealer_before(0), 2);
+    }
+
+    #[test]
+    fn the_dealer_sits_out_at_a_table_of_four() {
+        let rules = Ruleset {
+            seats: 4,
+            ..Ruleset::default()
+        };
+        for dealer in 0..4 {
+            assert_eq!(rules.position_of(dealer, dealer), None);
+
+            let seats: Vec<usize> = (0..HAND_PLAYERS)
+                .map(|position| rules.seat_of(dealer, position))
+                .collect();
+            assert!(!seats.contains(&dealer));
+            for (position, &seat) in seats.iter().enumerate() {
+                assert_eq!(rules.position_of(dealer, seat), Some(position));
+            }
+
+            let first = rules.first_position(dealer);
+            assert_eq!(rules.seat_of(dealer, first), rules.next_dealer(dealer));
+        }
+        assert_eq!(rules.dealer_before(0), 3);
+        assert_eq!(rules.position_of(0, 4), None);
+    }
+}
@@ -0,0 +1,144 @@
+use serde::Serialize;
+
+use super::game::PlayerScore;
+
+/// Worth of one point of bula, in soups.
+pub const BULA_VALUE: i64 = 10;
+
+/// Final reckoning of a score sheet, settled pair by pair.
+///
+/// Between two players the soups they wrote on each other cancel out, and the one with the
+/// higher bula pays their share of the difference at `BULA_VALUE` per point, a share being one
+/// part in as many as there are seats (the rest of each player's bula is settled with the
+/// other players). Every pair is rounded to a whole soup on its own, so the results always
+/// add up to zero.
+#[derive(Debug, Clone, Serialize)]
+pub struct Settlement {
+    /// `debts[debtor][creditor]`: soups `debtor` pays `creditor`. At most one direction of a
+    /// pair is non-zero.
+    pub debts: Vec<Vec<i64>>,
+    /// Net result of each player, positive for a win.
+    pub results: Vec<i64>,
+}
+
+impl Settlement {
+    pub fn new(score: &[PlayerScore]) -> Self {
+        let seats = score.len();
+
+        let mut debts = vec![vec![0; seats]; seats];
+        let pairs = (0..seats).flat_map(|a| (a + 1..seats).map(move |b| (a, b)));
+        for (a, b) in pairs {
+            let owed = owed_between(score, a, b);
+            if owed > 0 {
+                debts[b][a] = owed;
+            } else {
+                debts[a][b] = -owed;
+            }
+        }
+
+        let results = (0..seats)
+            .map(|player| {
+                (0..seats)
+                    .map(|other| debts[other][player] - debts[player][other])
+                    .sum()
+            })
+            .collect();
+
+        Self { debts, results }
+    }
+}
+
+/// What `b` owes `a`, negative when `a` is the one paying.
+fn owed_between(score: &[PlayerScore], a: usize, b: usize) -> i64 {
+    let seats = score.len() as i64;
+    let soups = score[a].soups_on(b) as i64 - score[b].soups_on(a) as i64;
+    let bula = (score[b].bulls() - score[a].bulls()) as i64;
+
+    // Kept in fractions of a soup until the end to round only once.
+    round_fraction(soups * seats + bula * BULA_VALUE, seats)
+}
+
+/// Rounds `numerator / denominator` to the nearest whole, halves away from zero. Symmetric
+/// around zero so the pair rounds to the same amount whichever way round it is computed.
+fn round_fraction(numerator: i64, denominator: i64) -> i64 {
+    numerator.signum() * ((numerator.abs() * 2 + denominator) / (denominator * 2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Builds a score sheet from `(bula, soups by target seat)` for each seat.
+    fn sheet(rows: &[(i32, &[u32])]) -> Vec<PlayerScore> {
+        rows.iter()
+            .map(|(bulls, soups)| {
+                serde_json::from_value(json!({ "bulls": bulls, "soups": soups })).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn three_players_settle_pair_by_pair() {
+        // Bula closed at 0, 12 and -6 (the last one went over the mountain's end). Soups:
+        // A wrote 40 on B and 20 on C, B wrote 10 on A and 30 on C, C wrote 50 on A.
+        let score = sheet(&[(0, &[0, 40, 20]), (12, &[10, 0, 30]), (-6, &[50, 0, 0])]);
+        let settlement = Settlement::new(&score);
+
+        // A-B: (40 - 10) + 12 * 10 / 3 = 70 from B. A-C: (20 - 50) - 6 * 10 / 3 = -50, A pays.
+        // B-C: (30 - 0) - 18 * 10 / 3 = -30, B pays.
+        assert_eq!(
+            settlement.debts,
+            vec![vec![0, 0, 50], vec![70, 0, 30], vec![0, 0, 0]]
+        );
+        assert_eq!(settlement.results, vec![20, -100, 80]);
+    }
+
+    #[test]
+    fn pairs_round_to_whole_soups() {
+        // One point of bula is 10 / 3 soups to each of the other two.
+        let score = sheet(&[(0, &[0, 0, 0]), (1, &[0, 0, 0]), (0, &[0, 0, 0])]);
+        let settlement = Settlement::new(&score);
+
+        assert_eq!(settlement.debts[1], vec![3, 0, 3]);
+        assert_eq!(settlement.results, vec![3, -6, 3]);
+    }
+
+    #[test]
+    fn four_players_round_halves_away_from_zero() {
+        // One point of bula is 2.5 soups to each of the other three.
+        let score = sheet(&[
+            (0, &[0, 0, 0, 0]),
+            (0, &[0, 0, 0, 0]),
+            (0, &[0, 0, 0, 0]),
+            (1, &[0, 0, 0, 0]),
+        ]);
+        let settlement = Settlement::new(&score);
+
+        assert_eq!(settlement.debts[3], vec![3, 3, 3, 0]);
+        assert_eq!(settlement.results, vec![3, 3, 3, -9]);
+    }
+
+    #[test]
+    fn four_player_sheet_adds_up_to_zero() {
+        let score = sheet(&[
+            (4, &[0, 24, 8, 0]),
+            (-2, &[16, 0, 0, 36]),
+            (10, &[0, 6, 0, 12]),
+            (0, &[30, 0, 14, 0]),
+        ]);
+        let settlement = Settlement::new(&score);
+
+        // A-B: (24 - 16) * 4 - 6 * 10 = -28, 7 from A. C-D: (12 - 14) * 4 - 10 * 10 = -108,
+        // 27 from C.
+        assert_eq!(settlement.debts[0][1], 7);
+        assert_eq!(settlement.debts[2][3], 27);
+        assert_eq!(settlement.results.iter().sum::<i64>(), 0);
+        for (debtor, debts) in settlement.debts.iter().enumerate() {
+            for (creditor, &debt) in debts.iter().enumerate() {
+                assert!(debt >= 0);
+                assert!(debt == 0 || settlement.debts[creditor][debtor] == 0);
+            }
+        }
+    }
+}
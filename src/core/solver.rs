@@ -0,0 +1,252 @@
+use std::collections::HashMap;
+
+use super::{
+    card_set::CardSet,
+    game::turn_inc,
+    playing::RoundState,
+    types::{Card, CardSuit},
+};
+
+/*
+   Double dummy search over the tricks left in a hand, with every hand known, used to check
+   claims in ranked games.
+
+   It answers a yes or no question, whether the declarer can be sure of a number of good tricks
+   however the defenders play, which cuts the search off as soon as one side has a way through.
+   A good trick is one the declarer takes, or in betl one they don't. Positions at the start of
+   a trick are remembered, and of cards next to each other in rank in one hand only one is
+   tried, since they play the same.
+*/
+pub struct Solver {
+    trump: Option<CardSuit>,
+    declarer: usize,
+    /// The declarer is trying not to take tricks, as in betl.
+    misere: bool,
+    seen: HashMap<([CardSet; 3], usize, u32), bool>,
+}
+
+impl Solver {
+    pub fn new(trump: Option<CardSuit>, declarer: usize, misere: bool) -> Self {
+        Self {
+            trump,
+            declarer,
+            misere,
+            seen: HashMap::new(),
+        }
+    }
+
+    /// Whether the declarer can be sure of at least `need` good tricks from here on, with
+    /// `round` the trick on the table and `turn` the next to play to it.
+    pub fn secures(
+        &mut self,
+        hands: [CardSet; 3],
+        round: RoundState,
+        turn: usize,
+        need: u32,
+    ) -> bool {
+        if need == 0 {
+            return true;
+        }
+        if need > hands[turn].len() as u32 {
+            return false;
+        }
+
+        let key = (hands, turn, need);
+        let at_trick_start = round.lead_suit().is_none();
+        if at_trick_start && let Some(&known) = self.seen.get(&key) {
+            return known;
+        }
+
+        let declarer_plays = turn == self.declarer;
+        let mut result = !declarer_plays;
+        for card in self.moves(&hands, &round, turn) {
+            let mut hands = hands;
+            hands[turn].remove(card);
+            let round = round.play_card(card, turn);
+
+            let secured = if round.is_round_over() {
+                let winner = round.winner(self.trump);
+                let good = (winner == self.declarer) != self.misere;
+                self.secures(hands, RoundState::default(), winner, need - good as u32)
+            } else {
+                self.secures(hands, round, turn_inc(turn), need)
+            };
+
+            // The declarer needs one way through, the defenders one way to stop them
+            if secured == declarer_plays {
+                result = secured;
+                break;
+            }
+        }
+
+        if at_trick_start {
+            self.seen.insert(key, result);
+        }
+        result
+    }
+
+    /// Cards worth trying for `turn`, leaving out those next in rank to a higher card of the
+    /// same hand among the cards still in play.
+    fn moves(&self, hands: &[CardSet; 3], round: &RoundState, turn: usize) -> Vec<Card> {
+        let hand = hands[turn];
+        let legal = self.legal(hand, round.lead_suit());
+        let in_play = hands.iter().fold(round.cards(), |all, &h| all | h);
+
+        let mut moves = Vec::new();
+        for suit in CardSuit::ALL {
+            let mut cards = in_play.in_suit(suit).iter().peekable();
+            while let Some(card) = cards.next() {
+                let plays_as_next = cards.peek().is_some_and(|&next| hand.contains(next));
+                if legal.contains(card) && !plays_as_next {
+                    moves.push(card);
+                }
+            }
+        }
+
+        moves
+    }
+
+    /// Follow the lead suit if you can, otherwise trump if you can.
+    fn legal(&self, hand: CardSet, lead_suit: Option<CardSuit>) -> CardSet {
+        match lead_suit {
+            None => hand,
+            Some(suit) if hand.has_suit(suit) => hand.in_suit(suit),
+            Some(_) => match self.trump {
+                Some(trump) if hand.has_suit(trump) => hand.in_suit(trump),
+                _ => hand,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng, rngs::StdRng, seq::SliceRandom};
+
+    fn hand(s: &str) -> CardSet {
+        s.parse().unwrap()
+    }
+
+    fn hands(s: [&str; 3]) -> [CardSet; 3] {
+        s.map(hand)
+    }
+
+    /// Most good tricks the declarer can be sure of, searching every line of play.
+    fn best(
+        hands: [CardSet; 3],
+        round: RoundState,
+        turn: usize,
+        trump: Option<CardSuit>,
+        declarer: usize,
+        misere: bool,
+    ) -> u32 {
+        let hand = hands[turn];
+        if hand.is_empty() {
+            return 0;
+        }
+
+        let legal = match round.lead_suit() {
+            Some(suit) if hand.has_suit(suit) => hand.in_suit(suit),
+            Some(_) if trump.is_some_and(|trump| hand.has_suit(trump)) => {
+                hand.in_suit(trump.unwrap())
+            }
+            _ => hand,
+        };
+
+        let outcomes = legal.iter().map(|card| {
+            let mut hands = hands;
+            hands[turn].remove(card);
+            let round = round.play_card(card, turn);
+
+            if round.is_round_over() {
+                let winner = round.winner(trump);
+                let good = (winner == declarer) != misere;
+                good as u32
+                    + best(
+                        hands,
+                        RoundState::default(),
+                        winner,
+                        trump,
+                        declarer,
+                        misere,
+                    )
+            } else {
+                best(hands, round, turn_inc(turn), trump, declarer, misere)
+            }
+        });
+
+        if turn == declarer {
+            outcomes.max().unwrap()
+        } else {
+            outcomes.min().unwrap()
+        }
+    }
+
+    #[test]
+    fn top_trumps_take_every_trick() {
+        let hands = hands(["♠AKQ", "♠987", "♥AKQ"]);
+        let mut solver = Solver::new(Some(CardSuit::Spades), 0, false);
+
+        assert!(solver.secures(hands, RoundState::default(), 1, 3));
+        assert!(!solver.secures(hands, RoundState::default(), 1, 4));
+    }
+
+    #[test]
+    fn defenders_take_the_tricks_they_can() {
+        // The A♠ comes first, but the 7♥ can't win whoever leads to it
+        let hands = hands(["♠A ♥7", "♠K ♥A", "♠Q ♥K"]);
+        let mut solver = Solver::new(None, 0, false);
+
+        assert!(solver.secures(hands, RoundState::default(), 0, 1));
+        assert!(!solver.secures(hands, RoundState::default(), 0, 2));
+    }
+
+    #[test]
+    fn trick_on_the_table_counts() {
+        // In betl, with the K♥ led, the declarer has to take the trick with their A♥
+        let hands = hands(["♥A ♠7", "♥8 ♠8", "♠9"]);
+        let led: Card = "K♥".parse().unwrap();
+        let round = RoundState::default().play_card(led, 2);
+        let mut solver = Solver::new(None, 0, true);
+
+        assert!(!solver.secures(hands, round, 0, 2));
+        assert!(solver.secures(hands, round, 0, 1));
+    }
+
+    #[test]
+    fn betl_declarer_ducks_under_higher_cards() {
+        let hands = hands(["♠7 ♥7", "♠K ♥A", "♠Q ♥K"]);
+        let mut solver = Solver::new(None, 0, true);
+
+        assert!(solver.secures(hands, RoundState::default(), 0, 2));
+        assert!(solver.secures(hands, RoundState::default(), 1, 2));
+    }
+
+    #[test]
+    fn agrees_with_a_full_search() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut deck: Vec<Card> = CardSet::DECK.iter().collect();
+        let trumps = [None, Some(CardSuit::Spades), Some(CardSuit::Hearts)];
+
+        for _ in 0..200 {
+            deck.shuffle(&mut rng);
+            let hands: [CardSet; 3] =
+                std::array::from_fn(|i| deck[i * 4..i * 4 + 4].iter().copied().collect());
+            let trump = *trumps.choose(&mut rng).unwrap();
+            let misere = trump.is_none() && rng.gen_bool(0.5);
+            let declarer = rng.gen_range(0..3);
+            let turn = rng.gen_range(0..3);
+
+            let expected = best(hands, RoundState::default(), turn, trump, declarer, misere);
+            let mut solver = Solver::new(trump, declarer, misere);
+            for need in 0..=5 {
+                assert_eq!(
+                    solver.secures(hands, RoundState::default(), turn, need),
+                    need <= expected,
+                    "{need} good tricks of {hands:?}, declarer {declarer}, {trump:?} trumps"
+                );
+            }
+        }
+    }
+}
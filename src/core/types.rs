@@ -27,6 +27,28 @@ pub enum CardValue {
     Ace,
 }
 
+impl CardSuit {
+    pub const ALL: [CardSuit; 4] = [
+        CardSuit::Spades,
+        CardSuit::Diamonds,
+        CardSuit::Hearts,
+        CardSuit::Clubs,
+    ];
+}
+
+impl CardValue {
+    pub const ALL: [CardValue; 8] = [
+        CardValue::Seven,
+        CardValue::Eight,
+        CardValue::Nine,
+        CardValue::Ten,
+        CardValue::Jack,
+        CardValue::Queen,
+        CardValue::King,
+        CardValue::Ace,
+    ];
+}
+
 #[derive(PartialEq, PartialOrd, Clone, Copy, Debug, Deserialize, Serialize)]
 pub enum GameContract {
     Spades = 2,
@@ -1,10 +1,19 @@
-use crate::http::repos::{game::GameRepo, user::UserRepo};
-use crate::http::ws::ClientMap;
+use crate::http::repos::{
+    chat::ChatRepo, connection::ConnectionRepo, game::GameRepo, model::InstanceId,
+    request::RequestRepo, user::UserRepo,
+};
+use crate::http::{fanout::History, lobby::LobbyMap, outbox::OutboxMetrics, ws::ClientMap};
+use dashmap::DashMap;
+use sqlx::PgPool;
 use std::sync::Arc;
 
 pub mod controllers;
 pub mod error;
 pub mod extractors;
+pub mod fanout;
+pub mod invite;
+pub mod lobby;
+pub mod outbox;
 pub mod repos;
 pub mod routes;
 pub mod ws;
@@ -12,9 +21,35 @@ pub mod ws;
 #[derive(Debug, Clone)]
 pub(super) struct ApiContext {
     pub(super) config: ApiConfig,
+    pub(super) instance: InstanceId,
     pub(super) game_repo: Arc<GameRepo>,
     pub(super) user_repo: Arc<UserRepo>,
+    pub(super) chat_repo: Arc<ChatRepo>,
+    pub(super) connection_repo: Arc<ConnectionRepo>,
+    pub(super) request_repo: Arc<RequestRepo>,
     pub(super) clients: Arc<ClientMap>,
+    pub(super) lobby: Arc<LobbyMap>,
+    pub(super) history: Arc<History>,
+    pub(super) metrics: Arc<OutboxMetrics>,
+}
+
+impl ApiContext {
+    /// A new instance on the database behind `pool`.
+    pub(super) fn new(config: ApiConfig, pool: PgPool) -> Self {
+        Self {
+            config,
+            instance: InstanceId::new_v4(),
+            game_repo: Arc::new(GameRepo::new(pool.clone())),
+            user_repo: Arc::new(UserRepo::new(pool.clone())),
+            chat_repo: Arc::new(ChatRepo::new(pool.clone())),
+            connection_repo: Arc::new(ConnectionRepo::new(pool.clone())),
+            request_repo: Arc::new(RequestRepo::new(pool)),
+            clients: Arc::new(DashMap::new()),
+            lobby: Arc::new(DashMap::new()),
+            history: Arc::new(DashMap::new()),
+            metrics: Arc::new(OutboxMetrics::default()),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -1,9 +1,16 @@
 use anyhow::Result;
 use axum::{
     Json,
-    extract::{Path, State},
+    extract::{Path, Query, State},
+    http::StatusCode,
 };
-use prefsty::core::game::new_game;
+use prefsty::core::{
+    game::{GameState, new_game},
+    ledger::ScoreSheet,
+    record::{GameRecord, RecordHeader, STANDARD_RULESET},
+    settlement::Settlement,
+};
+use rand::seq::SliceRandom;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -11,73 +18,732 @@ use crate::http::{
     ApiContext,
     error::AppError,
     extractors::AuthUser,
-    repos::model::{Game, GameId, UserSafe, UserSafeIdx},
+    invite::{self, Invite},
+    lobby::{self, LobbyEventKind},
+    repos::{
+        error::DbError,
+        model::{
+            Game, GameId, GameSettings, GameStatus, GameSummary, LobbyFilter, Spectators, UserId,
+            UserSafeIdx,
+        },
+    },
+    ws,
 };
 
+/// Lists the lobby a page at a time, e.g. `GET /games?status=Waiting&free_seats=1&offset=20`.
 #[axum::debug_handler]
-pub async fn get_all(_: AuthUser, ctx: State<ApiContext>) -> Result<Json<Vec<Game>>, AppError> {
+pub async fn get_all(
+    user: AuthUser,
+    ctx: State<ApiContext>,
+    Query(filter): Query<LobbyFilter>,
+) -> Result<Json<Vec<GameSummary>>, AppError> {
+    if filter.offset < 0 {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "offset can't be negative",
+        ));
+    }
+
     let games_repo = &ctx.game_repo;
-    let games = games_repo.get_all().await?;
+    let games = games_repo.get_all(user.user_id, &filter).await?;
 
     Ok(Json(games))
 }
 
+/// A game as the user asking for it sees it: their own hand and the open ones, nothing of the
+/// record, which has every hand dealt.
+#[derive(Serialize)]
+pub struct GameView {
+    pub id: GameId,
+    pub status: GameStatus,
+    pub settings: Option<GameSettings>,
+    /// `None` before the deal, and for users the game has no spectators for.
+    pub state: Option<serde_json::Value>,
+    pub resigned: Option<i16>,
+    pub private: bool,
+    pub created_by: UserId,
+}
+
 pub async fn get_by_id(
-    _: AuthUser,
+    user: AuthUser,
     ctx: State<ApiContext>,
     Path(game_id): Path<GameId>,
-) -> Result<Json<Game>, AppError> {
-    let games_repo = &ctx.game_repo;
-    let game = games_repo.get_by_id(game_id).await?;
+    Query(invite): Query<InviteKey>,
+) -> Result<Json<GameView>, AppError> {
+    let game = get_visible(&ctx, game_id, &user, &invite).await?;
+    let state = ws::view_for(&ctx, &game, user.user_id).await?;
 
-    Ok(Json(game))
+    Ok(Json(GameView {
+        id: game.id,
+        status: game.status,
+        settings: game.settings.map(|settings| settings.0),
+        state,
+        resigned: game.resigned,
+        private: game.private,
+        created_by: game.created_by,
+    }))
 }
 
 pub async fn get_joined_by_game_id(
-    _: AuthUser,
+    user: AuthUser,
     ctx: State<ApiContext>,
     Path(game_id): Path<GameId>,
+    Query(invite): Query<InviteKey>,
 ) -> Result<Json<Vec<UserSafeIdx>>, AppError> {
+    get_visible(&ctx, game_id, &user, &invite).await?;
     let games_repo = &ctx.game_repo;
     let joined_users = games_repo.get_joined_by_game_id(game_id).await?;
 
     Ok(Json(joined_users))
 }
 
+#[derive(Serialize, Deserialize, Default)]
+pub struct SeatData {
+    seat: Option<i16>,
+}
+
+/// What lets someone into a private game, sent along when joining or looking at one.
+#[derive(Serialize, Deserialize, Default)]
+pub struct InviteKey {
+    /// Invite code of a private game.
+    code: Option<String>,
+    /// Token of an invite link to a private game.
+    invite: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct JoinData {
+    seat: Option<i16>,
+    #[serde(flatten)]
+    invite: InviteKey,
+}
+
+/// Gets a game the user may look at: any public game, and a private one they created, sit in,
+/// have a seat reserved in or hold an invite to.
+pub(crate) async fn get_visible(
+    ctx: &ApiContext,
+    game_id: GameId,
+    user: &AuthUser,
+    invite: &InviteKey,
+) -> Result<Game, AppError> {
+    let games_repo = &ctx.game_repo;
+    let game = games_repo.get_by_id(game_id).await?;
+
+    if game.private
+        && game.created_by != user.user_id
+        && !games_repo.is_expected(game_id, user.user_id).await?
+        && !is_invited(ctx, game_id, invite).await?
+    {
+        return Err(AppError::new(StatusCode::FORBIDDEN, "game is invite only"));
+    }
+
+    Ok(game)
+}
+
+/// Takes the seat asked for, or the lowest free one. Private games need an invite code or
+/// link, unless the user created the game or has a seat reserved. Returns the seat taken.
 pub async fn join(
     user: AuthUser,
     ctx: State<ApiContext>,
     Path(game_id): Path<GameId>,
+    data: Option<Json<JoinData>>,
+) -> Result<Json<i16>, AppError> {
+    let games_repo = &ctx.game_repo;
+    let game = games_repo.get_by_id(game_id).await?;
+    waiting(&game)?;
+
+    let Json(data) = data.unwrap_or_default();
+    let invited = !game.private
+        || game.created_by == user.user_id
+        || is_invited(&ctx, game_id, &data.invite).await?;
+    let seat = match games_repo
+        .join(game_id, user.user_id, data.seat, invited)
+        .await
+    {
+        Err(DbError::NoAvailableSlot) if !invited => {
+            return Err(AppError::new(StatusCode::FORBIDDEN, "game is invite only"));
+        }
+        seat => seat?,
+    };
+
+    let joined = games_repo.get_joined_by_game_id(game_id).await?;
+    ws::broadcast_seats(&ctx, game_id, &joined).await;
+    lobby::publish(&ctx, game_id, LobbyEventKind::Joined).await;
+
+    Ok(Json(seat))
+}
+
+/// Whether the current invite code of the game was given, or a link made with it.
+async fn is_invited(ctx: &ApiContext, game_id: GameId, data: &InviteKey) -> Result<bool, AppError> {
+    let Some(code) = ctx.game_repo.get_invite_code(game_id).await? else {
+        return Ok(false);
+    };
+
+    let by_code = data
+        .code
+        .as_deref()
+        .is_some_and(|given| given.eq_ignore_ascii_case(&code));
+    let by_link = data
+        .invite
+        .as_deref()
+        .and_then(|token| Invite::from_token(token, ctx))
+        .is_some_and(|invite| invite.game_id == game_id && invite.code == code);
+
+    Ok(by_code || by_link)
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct InviteData {
+    code: String,
+    /// Signed token for invite links, good for a week or until the code changes.
+    token: String,
+}
+
+/// Makes a new invite code for a private game, which stops the old code and links made with it
+/// from working.
+pub async fn invite(
+    user: AuthUser,
+    ctx: State<ApiContext>,
+    Path(game_id): Path<GameId>,
+) -> Result<Json<InviteData>, AppError> {
+    let games_repo = &ctx.game_repo;
+    let game = games_repo.get_by_id(game_id).await?;
+    created_by(&game, &user)?;
+    if !game.private {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "public games need no invite",
+        ));
+    }
+    if game.status.is_over() {
+        return Err(AppError::new(StatusCode::CONFLICT, "game is already over"));
+    }
+
+    let code = invite::new_code();
+    games_repo.set_invite_code(game_id, Some(&code)).await?;
+    let token = Invite::new(game_id, code.clone()).to_jwt(&ctx);
+
+    Ok(Json(InviteData { code, token }))
+}
+
+/// Revokes the invite code of a game along with every link made with it.
+pub async fn revoke_invite(
+    user: AuthUser,
+    ctx: State<ApiContext>,
+    Path(game_id): Path<GameId>,
+) -> Result<Json<()>, AppError> {
+    let games_repo = &ctx.game_repo;
+    created_by(&games_repo.get_by_id(game_id).await?, &user)?;
+    games_repo.set_invite_code(game_id, None).await?;
+
+    Ok(Json(()))
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ReserveData {
+    seat: i16,
+    /// User to keep the seat for, `None` to free it up for anyone.
+    username: Option<String>,
+}
+
+/// Keeps a free seat for a given user, who can take it without an invite.
+pub async fn reserve(
+    user: AuthUser,
+    ctx: State<ApiContext>,
+    Path(game_id): Path<GameId>,
+    Json(data): Json<ReserveData>,
+) -> Result<Json<()>, AppError> {
+    let games_repo = &ctx.game_repo;
+    let game = games_repo.get_by_id(game_id).await?;
+    created_by(&game, &user)?;
+    waiting(&game)?;
+
+    let reserved_for = match &data.username {
+        Some(username) => Some(ctx.user_repo.get_by_username(username).await?.id),
+        None => None,
+    };
+    if !games_repo.reserve(game_id, data.seat, reserved_for).await? {
+        return Err(AppError::new(
+            StatusCode::CONFLICT,
+            "seat is taken or doesn't exist",
+        ));
+    }
+
+    Ok(Json(()))
+}
+
+/// Gets up from the table before the game starts.
+pub async fn leave(
+    user: AuthUser,
+    ctx: State<ApiContext>,
+    Path(game_id): Path<GameId>,
 ) -> Result<Json<()>, AppError> {
     let games_repo = &ctx.game_repo;
-    games_repo.join(game_id, user.user_id).await?;
+    waiting(&games_repo.get_by_id(game_id).await?)?;
+
+    if !games_repo.leave(game_id, user.user_id).await? {
+        return Err(AppError::new(
+            StatusCode::FORBIDDEN,
+            "user not in this game",
+        ));
+    }
+
+    let joined = games_repo.get_joined_by_game_id(game_id).await?;
+    ws::broadcast_seats(&ctx, game_id, &joined).await;
+    lobby::publish(&ctx, game_id, LobbyEventKind::Left).await;
+
+    Ok(Json(()))
+}
+
+/// Moves to another seat before the game starts. A free seat is taken straight away. For a
+/// taken one this asks its player to swap, and they swap once that player asks back. No seat
+/// takes back the ask.
+pub async fn change_seat(
+    user: AuthUser,
+    ctx: State<ApiContext>,
+    Path(game_id): Path<GameId>,
+    Json(data): Json<SeatData>,
+) -> Result<Json<()>, AppError> {
+    let games_repo = &ctx.game_repo;
+    let game = games_repo.get_by_id(game_id).await?;
+    waiting(&game)?;
+
+    let joined = games_repo.get_joined_by_game_id(game_id).await?;
+    let seat = seat_of(&joined, &user)?;
+
+    match data.seat {
+        None => games_repo.set_swap_to(game_id, user.user_id, None).await?,
+        Some(to) if to == seat => {
+            return Err(AppError::new(
+                StatusCode::BAD_REQUEST,
+                "already sitting there",
+            ));
+        }
+        Some(to) if !(0..game.rules().seats as i16).contains(&to) => {
+            return Err(AppError::new(StatusCode::BAD_REQUEST, "no such seat"));
+        }
+        Some(to) => match joined.iter().find(|u| u.idx == to) {
+            None => {
+                if !games_repo.move_seat(game_id, user.user_id, to).await? {
+                    return Err(AppError::new(StatusCode::CONFLICT, "seat was just taken"));
+                }
+            }
+            Some(other) if other.swap_to == Some(seat) => {
+                let swapped = games_repo
+                    .swap_seats(game_id, (user.user_id, seat), (other.id, to))
+                    .await?;
+                if !swapped {
+                    return Err(AppError::new(
+                        StatusCode::CONFLICT,
+                        "seats changed in the meantime",
+                    ));
+                }
+            }
+            Some(_) => {
+                games_repo
+                    .set_swap_to(game_id, user.user_id, Some(to))
+                    .await?
+            }
+        },
+    }
+
+    let joined = games_repo.get_joined_by_game_id(game_id).await?;
+    ws::broadcast_seats(&ctx, game_id, &joined).await;
 
     Ok(Json(()))
 }
 
 #[derive(Serialize, Deserialize)]
-pub struct NewGameSettings {
-    first: usize,
-    starting_score: u32,
-    num_refas: usize,
+pub struct CreateData {
+    #[serde(flatten)]
+    settings: GameSettings,
+    /// Hide the game from the lobby and let people in by invite only.
+    #[serde(default)]
+    private: bool,
 }
 
+/// Opens a table that waits for its seats to fill up. Nothing is dealt until everyone is ready.
 #[axum::debug_handler]
 pub async fn create(
     user: AuthUser,
     ctx: State<ApiContext>,
-    Json(settings): Json<NewGameSettings>,
-) -> Result<Json<()>, AppError> {
-    let new_game = new_game(settings.first, settings.starting_score, settings.num_refas);
+    Json(CreateData { settings, private }): Json<CreateData>,
+) -> Result<Json<GameId>, AppError> {
+    if !settings.rules.is_valid() || settings.first >= settings.rules.seats {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "tables seat 3 or 4 players",
+        ));
+    }
+    if settings.rules.ranked && matches!(settings.spectators, Spectators::AllHands { .. }) {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "ranked games don't show spectators the hands",
+        ));
+    }
 
+    let id = Uuid::new_v4();
     let games_repo = &ctx.game_repo;
     games_repo
         .create(Game {
-            id: Uuid::new_v4(),
-            state: new_game,
+            id,
+            status: GameStatus::Waiting,
+            settings: Some(sqlx::types::Json(settings)),
+            state: None,
+            record: None,
+            resigned: None,
+            private,
             created_by: user.user_id,
+            version: 0,
         })
         .await?;
+    lobby::publish(&ctx, id, LobbyEventKind::Created).await;
+
+    Ok(Json(id))
+}
+
+/// Deals the first hand of a game with a fresh record of it.
+fn deal(settings: &GameSettings) -> (GameState, GameRecord) {
+    let state = new_game(
+        settings.first,
+        settings.starting_score,
+        settings.num_refas,
+        settings.rules,
+    );
+    let record = GameRecord::new(
+        RecordHeader {
+            players: Vec::new(),
+            ruleset: STANDARD_RULESET.into(),
+            bula: settings.starting_score,
+            refas: settings.num_refas,
+            first: settings.first,
+            rules: settings.rules,
+        },
+        &state,
+    );
+
+    (state, record)
+}
+
+fn created_by(game: &Game, user: &AuthUser) -> Result<(), AppError> {
+    if game.created_by != user.user_id {
+        return Err(AppError::new(
+            StatusCode::FORBIDDEN,
+            "only the creator of the game can do this",
+        ));
+    }
+
+    Ok(())
+}
+
+fn waiting(game: &Game) -> Result<(), AppError> {
+    if game.status != GameStatus::Waiting {
+        return Err(AppError::new(
+            StatusCode::CONFLICT,
+            "game has already started",
+        ));
+    }
+
+    Ok(())
+}
+
+/// Seat of `user` at the table, an error when they haven't joined.
+fn seat_of(joined: &[UserSafeIdx], user: &AuthUser) -> Result<i16, AppError> {
+    joined
+        .iter()
+        .find(|u| u.id == user.user_id)
+        .map(|u| u.idx)
+        .ok_or(AppError::new(
+            StatusCode::FORBIDDEN,
+            "user not in this game",
+        ))
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ReadyData {
+    ready: bool,
+}
+
+/// Marks the player ready or not. The game starts as soon as every seat is taken and ready.
+pub async fn ready(
+    user: AuthUser,
+    ctx: State<ApiContext>,
+    Path(game_id): Path<GameId>,
+    Json(data): Json<ReadyData>,
+) -> Result<Json<()>, AppError> {
+    let games_repo = &ctx.game_repo;
+    let mut game = games_repo.get_by_id(game_id).await?;
+    waiting(&game)?;
+
+    let joined = games_repo.get_joined_by_game_id(game_id).await?;
+    seat_of(&joined, &user)?;
+    games_repo
+        .set_ready(game_id, user.user_id, data.ready)
+        .await?;
+
+    let joined = games_repo.get_joined_by_game_id(game_id).await?;
+    ws::broadcast_seats(&ctx, game_id, &joined).await;
+    let is_full = joined.len() == game.rules().seats;
+    if !is_full || !joined.iter().all(|u| u.ready) {
+        return Ok(Json(()));
+    }
+
+    // Imported games come with their state, new ones are dealt now
+    if game.state.is_none() {
+        let settings = game.settings.as_ref().ok_or(AppError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "game has nothing to deal from",
+        ))?;
+        let (state, record) = deal(settings);
+        game.state = Some(state);
+        game.record = Some(record);
+    }
+
+    let seating = game
+        .settings
+        .as_ref()
+        .is_some_and(|settings| settings.random_seats)
+        .then(|| {
+            let mut seating: Vec<UserId> = joined.iter().map(|u| u.id).collect();
+            seating.shuffle(&mut rand::thread_rng());
+            seating
+        });
+
+    if games_repo.start(&game, seating.as_deref()).await? {
+        let joined = games_repo.get_joined_by_game_id(game_id).await?;
+        ws::broadcast_seats(&ctx, game_id, &joined).await;
+        ws::broadcast_status(&ctx, game_id, GameStatus::InProgress).await;
+        ws::broadcast_state(&ctx, game_id).await;
+        lobby::publish(&ctx, game_id, LobbyEventKind::Started).await;
+    }
+
+    Ok(Json(()))
+}
+
+pub async fn pause(
+    user: AuthUser,
+    ctx: State<ApiContext>,
+    Path(game_id): Path<GameId>,
+) -> Result<Json<()>, AppError> {
+    change_status(
+        &ctx,
+        &user,
+        game_id,
+        GameStatus::InProgress,
+        GameStatus::Paused,
+    )
+    .await
+}
+
+pub async fn resume(
+    user: AuthUser,
+    ctx: State<ApiContext>,
+    Path(game_id): Path<GameId>,
+) -> Result<Json<()>, AppError> {
+    change_status(
+        &ctx,
+        &user,
+        game_id,
+        GameStatus::Paused,
+        GameStatus::InProgress,
+    )
+    .await
+}
+
+/// Lets a seated player move the game from `from` to `to` and tells the table.
+async fn change_status(
+    ctx: &ApiContext,
+    user: &AuthUser,
+    game_id: GameId,
+    from: GameStatus,
+    to: GameStatus,
+) -> Result<Json<()>, AppError> {
+    let games_repo = &ctx.game_repo;
+    let joined = games_repo.get_joined_by_game_id(game_id).await?;
+    seat_of(&joined, user)?;
+
+    if !games_repo.set_status(game_id, from, to).await? {
+        return Err(AppError::new(
+            StatusCode::CONFLICT,
+            format!("game is not {:?}", from),
+        ));
+    }
+
+    ws::broadcast_status(ctx, game_id, to).await;
+    Ok(Json(()))
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AbortVote {
+    vote: bool,
+}
+
+/// Votes to abandon the game, or takes the vote back. The game is abandoned once everyone
+/// seated has voted for it.
+pub async fn vote_abort(
+    user: AuthUser,
+    ctx: State<ApiContext>,
+    Path(game_id): Path<GameId>,
+    Json(data): Json<AbortVote>,
+) -> Result<Json<()>, AppError> {
+    let games_repo = &ctx.game_repo;
+    let game = games_repo.get_by_id(game_id).await?;
+    if game.status.is_over() {
+        return Err(AppError::new(StatusCode::CONFLICT, "game is already over"));
+    }
+
+    let joined = games_repo.get_joined_by_game_id(game_id).await?;
+    seat_of(&joined, &user)?;
+    games_repo
+        .set_abort_vote(game_id, user.user_id, data.vote)
+        .await?;
+
+    let joined = games_repo.get_joined_by_game_id(game_id).await?;
+    ws::broadcast_seats(&ctx, game_id, &joined).await;
+    if joined.iter().all(|u| u.abort_vote)
+        && games_repo
+            .set_status(game_id, game.status, GameStatus::Abandoned)
+            .await?
+    {
+        ws::broadcast_status(&ctx, game_id, GameStatus::Abandoned).await;
+        lobby::publish(&ctx, game_id, LobbyEventKind::Finished).await;
+    }
+
+    Ok(Json(()))
+}
 
+/// Gives the game up, which finishes it.
+pub async fn resign(
+    user: AuthUser,
+    ctx: State<ApiContext>,
+    Path(game_id): Path<GameId>,
+) -> Result<Json<()>, AppError> {
+    let games_repo = &ctx.game_repo;
+    let joined = games_repo.get_joined_by_game_id(game_id).await?;
+    let seat = seat_of(&joined, &user)?;
+
+    if !games_repo.resign(game_id, seat).await? {
+        return Err(AppError::new(
+            StatusCode::CONFLICT,
+            "game is not being played",
+        ));
+    }
+
+    ws::broadcast_status(&ctx, game_id, GameStatus::Finished).await;
+    lobby::publish(&ctx, game_id, LobbyEventKind::Finished).await;
     Ok(Json(()))
 }
+
+/// Usernames by seat, empty for seats nobody has taken.
+async fn seat_names(ctx: &ApiContext, game: &Game) -> Result<Vec<String>, AppError> {
+    let joined = ctx.game_repo.get_joined_by_game_id(game.id).await?;
+
+    let mut names = vec![String::new(); game.rules().seats];
+    for user in joined {
+        names[user.idx as usize] = user.username;
+    }
+
+    Ok(names)
+}
+
+pub async fn export_record(
+    user: AuthUser,
+    ctx: State<ApiContext>,
+    Path(game_id): Path<GameId>,
+    Query(invite): Query<InviteKey>,
+) -> Result<String, AppError> {
+    let game = get_visible(&ctx, game_id, &user, &invite).await?;
+    let players = seat_names(&ctx, &game).await?;
+    let mut record = game
+        .record
+        .ok_or(AppError::new(StatusCode::NOT_FOUND, "game has no record"))?;
+
+    record.header.players = players;
+    // the deal of the hand still being played shows everyone's cards
+    if !game.status.is_over() {
+        record.hands.pop();
+    }
+
+    record
+        .export()
+        .map_err(|e| AppError::new(StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))
+}
+
+pub async fn score_sheet(
+    user: AuthUser,
+    ctx: State<ApiContext>,
+    Path(game_id): Path<GameId>,
+    Query(invite): Query<InviteKey>,
+) -> Result<String, AppError> {
+    let game = get_visible(&ctx, game_id, &user, &invite).await?;
+
+    let players = seat_names(&ctx, &game).await?;
+    let state = started(&game)?;
+
+    let sheet = ScoreSheet {
+        ledger: state.ledger(),
+        score: state.score(),
+        players: &players,
+    };
+
+    Ok(sheet.to_string())
+}
+
+pub async fn settlement(
+    user: AuthUser,
+    ctx: State<ApiContext>,
+    Path(game_id): Path<GameId>,
+    Query(invite): Query<InviteKey>,
+) -> Result<Json<Settlement>, AppError> {
+    let game = get_visible(&ctx, game_id, &user, &invite).await?;
+
+    Ok(Json(Settlement::new(started(&game)?.score())))
+}
+
+fn started(game: &Game) -> Result<&GameState, AppError> {
+    game.state
+        .as_ref()
+        .ok_or(AppError::new(StatusCode::CONFLICT, "game has not started"))
+}
+
+#[axum::debug_handler]
+pub async fn import_record(
+    user: AuthUser,
+    ctx: State<ApiContext>,
+    text: String,
+) -> Result<Json<GameId>, AppError> {
+    let (mut record, state) = GameRecord::import(&text)
+        .map_err(|e| AppError::new(StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    // Players are taken from whoever joins on this server
+    record.header.players.clear();
+
+    let settings = GameSettings {
+        first: record.header.first,
+        starting_score: record.header.bula,
+        num_refas: record.header.refas,
+        rules: record.header.rules,
+        random_seats: false,
+        spectators: Default::default(),
+    };
+
+    // The imported game carries on once its seats are taken again
+    let id = Uuid::new_v4();
+    let games_repo = &ctx.game_repo;
+    games_repo
+        .create(Game {
+            id,
+            status: GameStatus::Waiting,
+            settings: Some(sqlx::types::Json(settings)),
+            state: Some(state),
+            record: Some(record),
+            resigned: None,
+            private: false,
+            created_by: user.user_id,
+            version: 0,
+        })
+        .await?;
+    lobby::publish(&ctx, id, LobbyEventKind::Created).await;
+
+    Ok(Json(id))
+}
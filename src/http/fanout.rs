@@ -0,0 +1,252 @@
+use std::{
+    collections::VecDeque,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgListener;
+use tokio::sync::Mutex;
+
+use crate::http::{
+    ApiContext, lobby,
+    repos::{
+        error::DbError,
+        model::{GameId, UserId},
+    },
+    ws,
+};
+
+/// Postgres channel the instances sharing a database publish on.
+const CHANNEL: &str = "fanout";
+/// Events kept per game for clients to resume from.
+const HISTORY_LENGTH: usize = 256;
+/// How long the events of a game nobody here is connected to are kept.
+const HISTORY_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Who among the connections a message goes out to gets it.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Audience {
+    /// Only these users, when set.
+    only: Option<Vec<UserId>>,
+    /// Never these users.
+    except: Vec<UserId>,
+    /// Whoever wrote the message, for the users that muted them to not get it. Looked up by
+    /// each instance among its own connections, since everyone who muted someone can be too
+    /// many to fit in a notification.
+    #[serde(default)]
+    sender: Option<UserId>,
+}
+
+impl Audience {
+    pub fn everyone() -> Self {
+        Self::default()
+    }
+
+    pub fn only(users: Vec<UserId>) -> Self {
+        Self {
+            only: Some(users),
+            ..Self::default()
+        }
+    }
+
+    pub fn except(users: Vec<UserId>) -> Self {
+        Self {
+            except: users,
+            ..Self::default()
+        }
+    }
+
+    pub fn sent_by(mut self, sender: UserId) -> Self {
+        self.sender = Some(sender);
+        self
+    }
+
+    pub fn sender(&self) -> Option<UserId> {
+        self.sender
+    }
+
+    pub fn includes(&self, user_id: UserId) -> bool {
+        self.only
+            .as_ref()
+            .is_none_or(|only| only.contains(&user_id))
+            && !self.except.contains(&user_id)
+    }
+}
+
+/// Something for the connections on every instance to hear about. Postgres caps a notification
+/// at 8000 bytes, so game states, which are much bigger, are loaded by each instance instead.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Event {
+    /// Everyone connected to the game gets their own view of it, as it's stored now.
+    State(GameId),
+    /// An outgoing message for some of those connected to a game.
+    Game {
+        game_id: GameId,
+        audience: Audience,
+        kind: serde_json::Value,
+    },
+    /// A message for some of those in the lobby.
+    Lobby { audience: Audience, message: String },
+}
+
+impl Event {
+    fn game_id(&self) -> Option<GameId> {
+        match self {
+            Event::State(game_id) | Event::Game { game_id, .. } => Some(*game_id),
+            Event::Lobby { .. } => None,
+        }
+    }
+}
+
+/// An event as published, game events numbered in the order they happened in their game.
+#[derive(Deserialize)]
+struct Notification {
+    seq: Option<i64>,
+    event: Event,
+}
+
+/// Hands the event to every instance, this one included, to deliver to their own connections.
+/// Whatever the event tells about is saved by then, so an event that can't be published is
+/// only logged: the clients that miss it catch up when they sync.
+pub async fn publish(state: &ApiContext, event: &Event) {
+    if let Err(err) = try_publish(state, event).await {
+        tracing::warn!("couldn't publish an event of {:?}: {err}", event.game_id());
+    }
+}
+
+async fn try_publish(state: &ApiContext, event: &Event) -> Result<(), DbError> {
+    let connection_repo = &state.connection_repo;
+    match event.game_id() {
+        Some(game_id) => {
+            let payload = serde_json::to_string(event)?;
+            connection_repo
+                .notify_game(CHANNEL, game_id, &payload)
+                .await
+        }
+        None => {
+            let payload = serde_json::json!({ "seq": null, "event": event }).to_string();
+            connection_repo.notify(CHANNEL, &payload).await
+        }
+    }
+}
+
+/// Events lately published for each game, for clients that dropped to catch up on.
+pub type History = DashMap<GameId, Arc<Mutex<GameHistory>>>;
+
+/// The last events of a game, numbered without gaps. Delivering an event and catching a client
+/// up both lock it, so a client catching up doesn't miss the events delivered meanwhile.
+#[derive(Debug)]
+pub struct GameHistory {
+    events: VecDeque<(i64, Event)>,
+    updated_at: Instant,
+}
+
+impl Default for GameHistory {
+    fn default() -> Self {
+        Self {
+            events: VecDeque::new(),
+            updated_at: Instant::now(),
+        }
+    }
+}
+
+impl GameHistory {
+    fn push(&mut self, seq: i64, event: Event) {
+        // a gap means notifications were lost, so what came before can't be replayed
+        if self.events.back().is_some_and(|&(last, _)| last + 1 != seq) {
+            self.events.clear();
+        }
+        if self.events.len() == HISTORY_LENGTH {
+            self.events.pop_front();
+        }
+        self.events.push_back((seq, event));
+        self.updated_at = Instant::now();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Events after `seq`, `None` when some of them aren't kept anymore.
+    pub fn since(&self, seq: i64) -> Option<Vec<&(i64, Event)>> {
+        match self.events.front() {
+            Some(&(first, _)) if first > seq + 1 => None,
+            _ => Some(self.events.iter().filter(|&&(s, _)| s > seq).collect()),
+        }
+    }
+}
+
+pub fn history_of(state: &ApiContext, game_id: GameId) -> Arc<Mutex<GameHistory>> {
+    state.history.entry(game_id).or_default().clone()
+}
+
+/// Listens for what the instances publish, which is then passed to `deliver`.
+pub async fn listener(pool: &sqlx::PgPool) -> Result<PgListener, sqlx::Error> {
+    let mut listener = PgListener::connect_with(pool).await?;
+    listener.listen(CHANNEL).await?;
+    Ok(listener)
+}
+
+/// Delivers what any instance publishes to the connections on this one.
+pub async fn deliver(state: ApiContext, mut listener: PgListener) {
+    loop {
+        // lost connections are picked up again on the next call, but what was published in the
+        // meantime is gone, so clients catch up by syncing
+        let notification = match listener.recv().await {
+            Ok(notification) => notification,
+            Err(err) => {
+                tracing::warn!("lost the fan-out listener: {err}");
+                tokio::time::sleep(ws::HEARTBEAT_INTERVAL).await;
+                continue;
+            }
+        };
+        let Ok(Notification { seq, event }) = serde_json::from_str(notification.payload()) else {
+            continue;
+        };
+
+        if let Event::Lobby { audience, message } = &event {
+            lobby::send_to(&state, audience, message);
+            continue;
+        }
+        let (Some(seq), Some(game_id)) = (seq, event.game_id()) else {
+            continue;
+        };
+
+        let history = history_of(&state, game_id);
+        let mut history = history.lock().await;
+        if let Err(err) = ws::deliver(&state, game_id, seq, &event).await {
+            tracing::warn!("couldn't deliver event {seq} of {game_id}: {err}");
+        }
+        history.push(seq, event);
+    }
+}
+
+/// Keeps the connections to this instance from being taken for ones left behind by an instance
+/// that went away, and forgets those. Also forgets the history of games left alone for a while,
+/// and requests too old to be repeated.
+pub async fn keep_alive(state: ApiContext) {
+    let connection_repo = &state.connection_repo;
+    let mut interval = tokio::time::interval(ws::HEARTBEAT_INTERVAL);
+
+    loop {
+        interval.tick().await;
+        let _ = connection_repo.keep_alive(state.instance).await;
+        let _ = connection_repo
+            .remove_stale(ws::HEARTBEAT_TIMEOUT.as_secs_f64())
+            .await;
+        let _ = state
+            .request_repo
+            .remove_expired(ws::REQUEST_WINDOW.as_secs_f64())
+            .await;
+
+        state.history.retain(|&game_id, history| {
+            ws::is_followed(&state, game_id)
+                // one that's in use is kept around
+                || history
+                    .try_lock()
+                    .map_or(true, |history| history.updated_at.elapsed() < HISTORY_TIMEOUT)
+        });
+    }
+}
@@ -0,0 +1,57 @@
+use hmac::{Hmac, Mac};
+use jwt::{SignWithKey, VerifyWithKey};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::Sha384;
+use time::OffsetDateTime;
+
+use crate::http::{ApiContext, repos::model::GameId};
+
+/// Invite to a private game, handed out as a signed token for invite links. It carries the
+/// game's invite code, so a new code or a revoked one makes old links stop working.
+#[derive(Serialize, Deserialize)]
+pub struct Invite {
+    pub game_id: GameId,
+    pub code: String,
+    expires_at: i64,
+}
+
+const DEFAULT_INVITE_LENGTH: time::Duration = time::Duration::weeks(1);
+
+/// Letters and digits that can't be mistaken for each other when read out.
+const CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const CODE_LENGTH: usize = 8;
+
+pub fn new_code() -> String {
+    let mut rng = rand::thread_rng();
+    (0..CODE_LENGTH)
+        .map(|_| CODE_ALPHABET[rng.gen_range(0..CODE_ALPHABET.len())] as char)
+        .collect()
+}
+
+impl Invite {
+    pub fn new(game_id: GameId, code: String) -> Self {
+        Self {
+            game_id,
+            code,
+            expires_at: (OffsetDateTime::now_utc() + DEFAULT_INVITE_LENGTH).unix_timestamp(),
+        }
+    }
+
+    pub fn to_jwt(&self, ctx: &ApiContext) -> String {
+        let hmac: Hmac<Sha384> =
+            Hmac::new_from_slice(ctx.config.hmac_key.as_bytes()).expect("Length can't be invalid");
+
+        self.sign_with_key(&hmac)
+            .expect("HMAC signing should be infallible")
+    }
+
+    /// The invite a token holds, `None` when it is forged or expired.
+    pub fn from_token(token_str: &str, ctx: &ApiContext) -> Option<Self> {
+        let hmac: Hmac<Sha384> = Hmac::new_from_slice(ctx.config.hmac_key.as_bytes()).ok()?;
+        let token: jwt::Token<jwt::Header, Invite, _> = token_str.verify_with_key(&hmac).ok()?;
+        let (_, invite) = token.into();
+
+        (invite.expires_at >= OffsetDateTime::now_utc().unix_timestamp()).then_some(invite)
+    }
+}
@@ -0,0 +1,244 @@
+use axum::{
+    extract::{
+        State, WebSocketUpgrade,
+        ws::{Message, WebSocket},
+    },
+    response::Response,
+};
+use dashmap::DashMap;
+use futures_util::{SinkExt, StreamExt};
+use serde::Serialize;
+
+use crate::http::{
+    ApiContext,
+    extractors::AuthUser,
+    fanout::{self, Audience, Event},
+    outbox::{self, Outbox},
+    repos::{
+        error::DbError,
+        model::{ConnectionId, GameId, GameSummary, UserId},
+    },
+};
+
+/// Lobby connections by their own id, as a user can have the lobby open in several tabs.
+pub type LobbyMap = DashMap<ConnectionId, LobbyClient>;
+
+#[derive(Debug)]
+pub struct LobbyClient {
+    user_id: UserId,
+    tx: Outbox,
+}
+
+pub async fn handler(
+    ws: WebSocketUpgrade,
+    user: AuthUser,
+    State(state): State<ApiContext>,
+) -> Response {
+    ws.on_upgrade(move |socket| handle_socket(socket, user.user_id, state))
+}
+
+/// Lobby clients only listen, so anything they send is dropped until they disconnect.
+async fn handle_socket(socket: WebSocket, user_id: UserId, state: ApiContext) {
+    let (mut ws_tx, mut ws_rx) = socket.split();
+    let (tx, mut rx) = outbox::channel(&state.metrics);
+    let connection = ConnectionId::new_v4();
+    state.lobby.insert(connection, LobbyClient { user_id, tx });
+
+    let mut write_task = tokio::spawn(async move {
+        while let Some(msg) = rx.recv().await {
+            if ws_tx.send(msg).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut read_task = tokio::spawn(async move {
+        while let Some(Ok(msg)) = ws_rx.next().await {
+            if let Message::Close(_) = msg {
+                break;
+            }
+        }
+    });
+
+    // a connection that fell behind is closed by the writer, which takes the reader with it
+    tokio::select! {
+        _ = &mut write_task => read_task.abort(),
+        _ = &mut read_task => write_task.abort(),
+    }
+
+    state.lobby.remove(&connection);
+}
+
+/// How many messages each lobby connection has waiting to be sent.
+pub(crate) fn queue_depths(state: &ApiContext) -> Vec<usize> {
+    state.lobby.iter().map(|client| client.tx.depth()).collect()
+}
+
+#[derive(Clone, Copy, Serialize)]
+pub enum LobbyEventKind {
+    Created,
+    Joined,
+    Left,
+    Started,
+    /// Played out, resigned or abandoned, as the status of the game tells.
+    Finished,
+}
+
+/// Something happened to a game in the lobby, sent with how the game looks after it.
+#[derive(Serialize)]
+struct LobbyEvent<'a> {
+    kind: LobbyEventKind,
+    game: &'a GameSummary,
+}
+
+/// Tells the lobby what happened to a game. The change is saved by then, so a lobby that
+/// can't be told only gets logged: it catches up the next time it lists the games.
+pub async fn publish(state: &ApiContext, game_id: GameId, kind: LobbyEventKind) {
+    if let Err(err) = try_publish(state, game_id, kind).await {
+        tracing::warn!("couldn't tell the lobby about {game_id}: {err}");
+    }
+}
+
+/// Private games are only told to their creator and the players sitting in them, who are the
+/// only ones to see them in the lobby.
+async fn try_publish(
+    state: &ApiContext,
+    game_id: GameId,
+    kind: LobbyEventKind,
+) -> Result<(), DbError> {
+    let game_repo = &state.game_repo;
+    let game = game_repo.get_by_id(game_id).await?;
+    let Some(summary) = game_repo.get_summary(&game).await? else {
+        return Ok(());
+    };
+
+    let outgoing = serde_json::to_string(&LobbyEvent {
+        kind,
+        game: &summary,
+    })
+    .unwrap();
+
+    let audience = if game.private {
+        let joined = game_repo.get_joined_by_game_id(game_id).await?;
+        let mut members: Vec<UserId> = joined.iter().map(|user| user.id).collect();
+        members.push(game.created_by);
+        Audience::only(members)
+    } else {
+        Audience::everyone()
+    };
+
+    let event = Event::Lobby {
+        audience,
+        message: outgoing,
+    };
+    fanout::publish(state, &event).await;
+    Ok(())
+}
+
+/// Hands a message published for the lobby to its audience on this instance.
+pub(crate) fn send_to(state: &ApiContext, audience: &Audience, message: &str) {
+    for client in state.lobby.iter() {
+        if audience.includes(client.user_id) {
+            let _ = client.tx.send(message.to_owned().into());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use sqlx::postgres::PgPoolOptions;
+
+    use super::*;
+    use crate::http::{
+        ApiConfig,
+        outbox::OutboxReceiver,
+        repos::model::{Game, GameStatus, User},
+    };
+
+    async fn user(state: &ApiContext) -> UserId {
+        let id = UserId::new_v4();
+        let user = User {
+            id,
+            username: format!("test-{id}"),
+            password: String::new(),
+            email: format!("{id}@test"),
+        };
+        state.user_repo.create(user).await.unwrap();
+        id
+    }
+
+    async fn game(state: &ApiContext, created_by: UserId, private: bool) -> GameId {
+        let game = Game {
+            id: GameId::new_v4(),
+            status: GameStatus::Waiting,
+            settings: None,
+            state: None,
+            record: None,
+            resigned: None,
+            private,
+            created_by,
+            version: 0,
+        };
+        let game_id = game.id;
+        state.game_repo.create(game).await.unwrap();
+        game_id
+    }
+
+    /// A lobby connection of `user_id`.
+    fn open_lobby(state: &ApiContext, user_id: UserId) -> OutboxReceiver {
+        let (tx, rx) = outbox::channel(&state.metrics);
+        state
+            .lobby
+            .insert(ConnectionId::new_v4(), LobbyClient { user_id, tx });
+        rx
+    }
+
+    /// Game the next event is about, `None` when nothing comes for a while.
+    async fn next_game(rx: &mut OutboxReceiver) -> Option<String> {
+        let msg = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .ok()??;
+        let msg: serde_json::Value = serde_json::from_str(&msg.into_text().unwrap()).unwrap();
+        msg["game"]["id"].as_str().map(str::to_owned)
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Postgres at DATABASE_URL"]
+    async fn private_games_are_told_to_their_members_only() {
+        let url = std::env::var("DATABASE_URL").unwrap();
+        let pool = PgPoolOptions::new().connect(&url).await.unwrap();
+        let config = ApiConfig {
+            hmac_key: "test".into(),
+        };
+        let state = ApiContext::new(config, pool.clone());
+        let listener = fanout::listener(&pool).await.unwrap();
+        tokio::spawn(fanout::deliver(state.clone(), listener));
+
+        let (creator, player, stranger) =
+            (user(&state).await, user(&state).await, user(&state).await);
+        let private = game(&state, creator, true).await;
+        let public = game(&state, creator, false).await;
+        state
+            .game_repo
+            .join(private, player, None, true)
+            .await
+            .unwrap();
+
+        // The creator has the lobby open twice, and both get told
+        let mut creator_rxs = [open_lobby(&state, creator), open_lobby(&state, creator)];
+        let mut player_rx = open_lobby(&state, player);
+        let mut stranger_rx = open_lobby(&state, stranger);
+
+        publish(&state, private, LobbyEventKind::Joined).await;
+        publish(&state, public, LobbyEventKind::Created).await;
+
+        for rx in creator_rxs.iter_mut().chain([&mut player_rx]) {
+            assert_eq!(next_game(rx).await, Some(private.to_string()));
+            assert_eq!(next_game(rx).await, Some(public.to_string()));
+        }
+        // The private game was skipped, so the public one comes first
+        assert_eq!(next_game(&mut stranger_rx).await, Some(public.to_string()));
+    }
+}
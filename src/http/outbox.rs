@@ -0,0 +1,315 @@
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicBool, AtomicU64, Ordering},
+};
+
+use axum::{Json, extract::State, extract::ws::Message};
+use serde::Serialize;
+use tokio::sync::{
+    Notify,
+    mpsc::{
+        self, Receiver, Sender,
+        error::{SendError, TryRecvError, TrySendError},
+    },
+};
+
+use crate::http::{ApiContext, extractors::AuthUser, lobby, ws};
+
+/// How many messages a connection can fall behind before it's dropped.
+pub const QUEUE_CAPACITY: usize = 64;
+
+/// Counts what happened to connections that couldn't keep up.
+#[derive(Debug, Default)]
+pub struct OutboxMetrics {
+    /// Game states that were replaced by a newer one before they were sent.
+    coalesced: AtomicU64,
+    /// Connections dropped for falling too far behind.
+    disconnected: AtomicU64,
+}
+
+#[derive(Debug)]
+struct Shared {
+    /// The newest game state, waiting for room in the queue.
+    latest_state: Mutex<Option<Message>>,
+    wake: Notify,
+    fell_behind: AtomicBool,
+    metrics: Arc<OutboxMetrics>,
+}
+
+impl Shared {
+    fn fall_behind(&self) {
+        if !self.fell_behind.swap(true, Ordering::Relaxed) {
+            self.metrics.disconnected.fetch_add(1, Ordering::Relaxed);
+            self.wake.notify_one();
+        }
+    }
+}
+
+/// Sending half of a connection's queue of outgoing messages.
+#[derive(Debug, Clone)]
+pub struct Outbox {
+    tx: Sender<Message>,
+    shared: Arc<Shared>,
+}
+
+/// Receiving half, read by the task writing to the socket.
+#[derive(Debug)]
+pub struct OutboxReceiver {
+    rx: Receiver<Message>,
+    shared: Arc<Shared>,
+}
+
+pub fn channel(metrics: &Arc<OutboxMetrics>) -> (Outbox, OutboxReceiver) {
+    let (tx, rx) = mpsc::channel(QUEUE_CAPACITY);
+    let shared = Arc::new(Shared {
+        latest_state: Mutex::new(None),
+        wake: Notify::new(),
+        fell_behind: AtomicBool::new(false),
+        metrics: metrics.clone(),
+    });
+
+    (
+        Outbox {
+            tx,
+            shared: shared.clone(),
+        },
+        OutboxReceiver { rx, shared },
+    )
+}
+
+impl Outbox {
+    /// Queues a message. A connection whose queue is full is dropped rather than buffered
+    /// without limit, and the message is handed back like for a closed one. So is one with a
+    /// state waiting aside, which would otherwise be sent after this message.
+    pub fn send(&self, msg: Message) -> Result<(), SendError<Message>> {
+        let latest_state = self.shared.latest_state.lock().unwrap();
+        if latest_state.is_some() {
+            self.shared.fall_behind();
+            return Err(SendError(msg));
+        }
+
+        match self.tx.try_send(msg) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(msg)) => {
+                self.shared.fall_behind();
+                Err(SendError(msg))
+            }
+            Err(TrySendError::Closed(msg)) => Err(SendError(msg)),
+        }
+    }
+
+    /// Queues a game state. Each state replaces the one before it, so one that doesn't fit
+    /// waits aside for the queue to drain, replaced by any newer state in the meantime.
+    pub fn send_state(&self, msg: Message) -> Result<(), SendError<Message>> {
+        let mut latest_state = self.shared.latest_state.lock().unwrap();
+        if latest_state.is_some() {
+            // the state that was waiting is outdated now
+            *latest_state = Some(msg);
+            self.shared
+                .metrics
+                .coalesced
+                .fetch_add(1, Ordering::Relaxed);
+            return Ok(());
+        }
+
+        match self.tx.try_send(msg) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(msg)) => {
+                *latest_state = Some(msg);
+                self.shared.wake.notify_one();
+                Ok(())
+            }
+            Err(TrySendError::Closed(msg)) => Err(SendError(msg)),
+        }
+    }
+
+    /// Messages waiting to be sent.
+    pub fn depth(&self) -> usize {
+        let waiting = self.shared.latest_state.lock().unwrap().is_some();
+        self.tx.max_capacity() - self.tx.capacity() + waiting as usize
+    }
+}
+
+impl OutboxReceiver {
+    /// Next message to send, or `None` once every sender is gone or the connection fell too far
+    /// behind and should be closed.
+    pub async fn recv(&mut self) -> Option<Message> {
+        loop {
+            if self.shared.fell_behind.load(Ordering::Relaxed) {
+                return None;
+            }
+            match self.rx.try_recv() {
+                Ok(msg) => return Some(msg),
+                Err(TryRecvError::Disconnected) => return None,
+                Err(TryRecvError::Empty) => {}
+            }
+            if let Some(msg) = self.shared.latest_state.lock().unwrap().take() {
+                return Some(msg);
+            }
+
+            tokio::select! {
+                msg = self.rx.recv() => match msg {
+                    Some(msg) => return Some(msg),
+                    None => return None,
+                },
+                _ = self.shared.wake.notified() => {},
+            }
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct QueueMetrics {
+    game_connections: usize,
+    lobby_connections: usize,
+    /// Messages waiting across all connections.
+    queued: usize,
+    max_queue_depth: usize,
+    coalesced: u64,
+    disconnected: u64,
+}
+
+pub async fn metrics(_user: AuthUser, State(state): State<ApiContext>) -> Json<QueueMetrics> {
+    let game_depths = ws::queue_depths(&state);
+    let lobby_depths = lobby::queue_depths(&state);
+    let depths = game_depths.iter().chain(&lobby_depths);
+
+    Json(QueueMetrics {
+        game_connections: game_depths.len(),
+        lobby_connections: lobby_depths.len(),
+        queued: depths.clone().sum(),
+        max_queue_depth: depths.max().copied().unwrap_or(0),
+        coalesced: state.metrics.coalesced.load(Ordering::Relaxed),
+        disconnected: state.metrics.disconnected.load(Ordering::Relaxed),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn text(s: String) -> Message {
+        Message::Text(s.into())
+    }
+
+    /// Fills the queue with chat messages `c0`, `c1` and so on.
+    fn fill(outbox: &Outbox) {
+        for i in 0..QUEUE_CAPACITY {
+            outbox.send(text(format!("c{i}"))).unwrap();
+        }
+    }
+
+    async fn recv_text(rx: &mut OutboxReceiver) -> Option<String> {
+        rx.recv()
+            .await
+            .map(|msg| msg.into_text().unwrap().to_string())
+    }
+
+    #[tokio::test]
+    async fn states_wait_aside_and_coalesce() {
+        let metrics = Arc::default();
+        let (outbox, mut rx) = channel(&metrics);
+        fill(&outbox);
+
+        for i in 0..3 {
+            outbox.send_state(text(format!("s{i}"))).unwrap();
+        }
+        assert_eq!(outbox.depth(), QUEUE_CAPACITY + 1);
+
+        for i in 0..QUEUE_CAPACITY {
+            assert_eq!(recv_text(&mut rx).await, Some(format!("c{i}")));
+        }
+        assert_eq!(recv_text(&mut rx).await, Some("s2".to_string()));
+        assert_eq!(metrics.coalesced.load(Ordering::Relaxed), 2);
+
+        // With the queue drained, messages go through in order again
+        outbox.send_state(text("s3".to_string())).unwrap();
+        outbox.send(text("after".to_string())).unwrap();
+        assert_eq!(recv_text(&mut rx).await, Some("s3".to_string()));
+        assert_eq!(recv_text(&mut rx).await, Some("after".to_string()));
+    }
+
+    #[tokio::test]
+    async fn message_behind_a_waiting_state_drops_the_connection() {
+        let metrics = Arc::default();
+        let (outbox, mut rx) = channel(&metrics);
+        fill(&outbox);
+        outbox.send_state(text("s0".to_string())).unwrap();
+
+        // It would have to go out after the state queued before it
+        assert!(outbox.send(text("finished".to_string())).is_err());
+        assert_eq!(recv_text(&mut rx).await, None);
+        assert_eq!(metrics.disconnected.load(Ordering::Relaxed), 1);
+    }
+
+    /// Every message carries a sequence number, states `s` and the rest `c`. A reader checks it
+    /// never sees one older than the last, and returns how many it got before the connection
+    /// was closed.
+    async fn read_in_order(mut rx: OutboxReceiver, delay: Duration) -> (usize, bool) {
+        let mut last = None;
+        let mut received = 0;
+        while let Some(msg) = recv_text(&mut rx).await {
+            let seq: u64 = msg[1..].parse().unwrap();
+            assert!(last < Some(seq), "{msg} came after {last:?}");
+            last = Some(seq);
+            received += 1;
+            if !delay.is_zero() {
+                tokio::time::sleep(delay).await;
+            }
+        }
+
+        (received, rx.shared.fell_behind.load(Ordering::Relaxed))
+    }
+
+    #[tokio::test]
+    async fn slow_readers_are_dropped_and_the_rest_keep_up() {
+        const READERS: usize = 100;
+        const ROUNDS: u64 = 2000;
+
+        let metrics: Arc<OutboxMetrics> = Arc::default();
+        let mut outboxes = Vec::new();
+        let mut readers = Vec::new();
+        for i in 0..READERS {
+            let (outbox, rx) = channel(&metrics);
+            let delay = if i % 2 == 0 {
+                Duration::ZERO
+            } else {
+                Duration::from_millis(5)
+            };
+            outboxes.push(outbox);
+            readers.push(tokio::spawn(read_in_order(rx, delay)));
+        }
+
+        // A state for every move and a chat message now and then
+        let mut max_depth = 0;
+        for seq in 0..ROUNDS {
+            for outbox in &outboxes {
+                if seq % 10 == 0 {
+                    let _ = outbox.send(text(format!("c{seq}")));
+                } else {
+                    let _ = outbox.send_state(text(format!("s{seq}")));
+                }
+                max_depth = max_depth.max(outbox.depth());
+            }
+            tokio::task::yield_now().await;
+        }
+        drop(outboxes);
+
+        let mut dropped = 0;
+        for (i, reader) in readers.into_iter().enumerate() {
+            let (received, fell_behind) = reader.await.unwrap();
+            if i % 2 == 0 {
+                assert!(!fell_behind);
+                assert_eq!(received, ROUNDS as usize);
+            } else {
+                assert!(fell_behind);
+                dropped += 1;
+            }
+        }
+
+        assert!(max_depth <= QUEUE_CAPACITY + 1);
+        assert_eq!(metrics.disconnected.load(Ordering::Relaxed), dropped);
+    }
+}
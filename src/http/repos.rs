@@ -1,4 +1,7 @@
+pub mod chat;
+pub mod connection;
 pub mod error;
 pub mod game;
 pub mod model;
+pub mod request;
 pub mod user;
@@ -0,0 +1,200 @@
+use crate::http::repos::{
+    error::DbError,
+    model::{ChatChannel, ChatMessage, GameId, UserId},
+};
+
+#[derive(Debug)]
+pub struct ChatRepo {
+    pool: sqlx::PgPool,
+}
+
+impl ChatRepo {
+    pub fn new(pool: sqlx::PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Saves a message, unless `user_id` already sent `rate` of them over the last `seconds`,
+    /// in any game. Returns `None` then.
+    pub async fn push(
+        &self,
+        game_id: GameId,
+        user_id: UserId,
+        channel: ChatChannel,
+        text: &str,
+        (rate, seconds): (i64, f64),
+    ) -> Result<Option<ChatMessage>, DbError> {
+        let mut tx = self.pool.begin().await?;
+
+        // Messages of the same user are counted one after another, from any tab or instance
+        sqlx::query!(
+            "SELECT id FROM users WHERE id = $1 FOR NO KEY UPDATE",
+            user_id
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let message = sqlx::query_as!(
+            ChatMessage,
+            r#"
+            WITH sent AS (
+                INSERT INTO chat_messages (game_id, user_id, channel, text)
+                SELECT $1, $2, $3, $4
+                WHERE  (
+                    SELECT COUNT(*) FROM chat_messages
+                    WHERE  user_id = $2 AND sent_at > now() - make_interval(secs => $6)
+                ) < $5
+                RETURNING id, user_id, channel, text, sent_at
+            )
+            SELECT sent.id, sent.user_id, users.username, sent.channel AS "channel: _", sent.text,
+                   EXTRACT(EPOCH FROM sent.sent_at)::bigint AS "sent_at!"
+            FROM   sent
+            JOIN   users ON users.id = sent.user_id
+            "#,
+            game_id,
+            user_id,
+            channel as _,
+            text,
+            rate,
+            seconds
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(message)
+    }
+
+    /// The last `limit` messages of a game, oldest first.
+    pub async fn get_recent(
+        &self,
+        game_id: GameId,
+        limit: i64,
+    ) -> Result<Vec<ChatMessage>, DbError> {
+        let mut messages = sqlx::query_as!(
+            ChatMessage,
+            r#"
+            SELECT   chat_messages.id, chat_messages.user_id, users.username,
+                     chat_messages.channel AS "channel: _", chat_messages.text,
+                     EXTRACT(EPOCH FROM chat_messages.sent_at)::bigint AS "sent_at!"
+            FROM     chat_messages
+            JOIN     users ON users.id = chat_messages.user_id
+            WHERE    chat_messages.game_id = $1
+            ORDER BY chat_messages.id DESC
+            LIMIT    $2
+            "#,
+            game_id,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        messages.reverse();
+        Ok(messages)
+    }
+
+    pub async fn mute(&self, user_id: UserId, muted_id: UserId) -> Result<(), DbError> {
+        sqlx::query!(
+            "INSERT INTO mutes (user_id, muted_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+            user_id,
+            muted_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn unmute(&self, user_id: UserId, muted_id: UserId) -> Result<(), DbError> {
+        sqlx::query!(
+            "DELETE FROM mutes WHERE user_id = $1 AND muted_id = $2",
+            user_id,
+            muted_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Users `user_id` muted.
+    pub async fn get_muted(&self, user_id: UserId) -> Result<Vec<UserId>, DbError> {
+        let muted = sqlx::query_scalar!("SELECT muted_id FROM mutes WHERE user_id = $1", user_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(muted)
+    }
+
+    /// Users among `among` that muted `user_id`.
+    pub async fn get_muted_by(
+        &self,
+        user_id: UserId,
+        among: &[UserId],
+    ) -> Result<Vec<UserId>, DbError> {
+        let muted_by = sqlx::query_scalar!(
+            "SELECT user_id FROM mutes WHERE muted_id = $1 AND user_id = ANY($2)",
+            user_id,
+            among
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(muted_by)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use prefsty::core::{game::new_game, ruleset::Ruleset};
+    use sqlx::postgres::PgPoolOptions;
+
+    use super::*;
+    use crate::http::repos::{
+        game::GameRepo,
+        model::{Game, GameStatus, User},
+        user::UserRepo,
+    };
+
+    #[tokio::test]
+    #[ignore = "needs Postgres at DATABASE_URL"]
+    async fn concurrent_messages_keep_to_the_rate() {
+        let url = std::env::var("DATABASE_URL").unwrap();
+        let pool = PgPoolOptions::new().connect(&url).await.unwrap();
+        let user_id = UserId::new_v4();
+        let user = User {
+            id: user_id,
+            username: format!("test-{user_id}"),
+            password: String::new(),
+            email: format!("{user_id}@test"),
+        };
+        UserRepo::new(pool.clone()).create(user).await.unwrap();
+        let game = Game {
+            id: GameId::new_v4(),
+            status: GameStatus::InProgress,
+            settings: None,
+            state: Some(new_game(0, 30, 0, Ruleset::default())),
+            record: None,
+            resigned: None,
+            private: false,
+            created_by: user_id,
+            version: 0,
+        };
+        let game_id = game.id;
+        GameRepo::new(pool.clone()).create(game).await.unwrap();
+
+        let repo = ChatRepo::new(pool);
+        let sends = (0..8).map(|i| {
+            let text = i.to_string();
+            let repo = &repo;
+            async move {
+                repo.push(game_id, user_id, ChatChannel::Table, &text, (3, 60.0))
+                    .await
+                    .unwrap()
+            }
+        });
+        let sent = futures_util::future::join_all(sends).await;
+
+        assert_eq!(sent.iter().filter(|m| m.is_some()).count(), 3);
+        assert_eq!(repo.get_recent(game_id, 100).await.unwrap().len(), 3);
+    }
+}
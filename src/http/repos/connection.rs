@@ -0,0 +1,123 @@
+use crate::http::repos::{
+    error::DbError,
+    model::{Connection, ConnectionId, GameId, InstanceId, UserId},
+};
+
+/// Connections to games across every server instance, which share them through the database.
+#[derive(Debug)]
+pub struct ConnectionRepo {
+    pool: sqlx::PgPool,
+}
+
+impl ConnectionRepo {
+    pub fn new(pool: sqlx::PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn add(
+        &self,
+        id: ConnectionId,
+        instance: InstanceId,
+        game_id: GameId,
+        user_id: UserId,
+    ) -> Result<(), DbError> {
+        sqlx::query!(
+            "INSERT INTO connections (id, instance, game_id, user_id) VALUES ($1, $2, $3, $4)",
+            id,
+            instance,
+            game_id,
+            user_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn remove(&self, id: ConnectionId) -> Result<(), DbError> {
+        sqlx::query!("DELETE FROM connections WHERE id = $1", id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn set_away(&self, id: ConnectionId, away: bool) -> Result<(), DbError> {
+        sqlx::query!("UPDATE connections SET away = $2 WHERE id = $1", id, away)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_by_game_id(&self, game_id: GameId) -> Result<Vec<Connection>, DbError> {
+        let connections = sqlx::query_as!(
+            Connection,
+            "SELECT user_id, away FROM connections WHERE game_id = $1",
+            game_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(connections)
+    }
+
+    /// Marks the connections of `instance` as still there.
+    pub async fn keep_alive(&self, instance: InstanceId) -> Result<(), DbError> {
+        sqlx::query!(
+            "UPDATE connections SET seen_at = now() WHERE instance = $1",
+            instance
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Forgets connections not kept alive over the last `seconds`, left behind by instances that
+    /// went away without closing them.
+    pub async fn remove_stale(&self, seconds: f64) -> Result<(), DbError> {
+        sqlx::query!(
+            "DELETE FROM connections WHERE seen_at < now() - make_interval(secs => $1)",
+            seconds
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Numbers `event` as the game's next one and hands both to every session listening on
+    /// `channel`. Numbering locks the game until the notification is sent, so they go out in
+    /// order.
+    pub async fn notify_game(
+        &self,
+        channel: &str,
+        game_id: GameId,
+        event: &str,
+    ) -> Result<(), DbError> {
+        sqlx::query!(
+            r#"
+            WITH next AS (UPDATE games SET seq = seq + 1 WHERE id = $2 RETURNING seq)
+            SELECT pg_notify($1, json_build_object('seq', next.seq, 'event', $3::text::json)::text)
+            FROM   next
+            "#,
+            channel,
+            game_id,
+            event
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Hands `payload` to every session listening on `channel`.
+    pub async fn notify(&self, channel: &str, payload: &str) -> Result<(), DbError> {
+        sqlx::query!("SELECT pg_notify($1, $2)", channel, payload)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+}
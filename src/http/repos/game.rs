@@ -1,6 +1,11 @@
+use sqlx::types::Json;
+
 use crate::http::repos::{
     error::DbError,
-    model::{Game, GameId, UserId, UserSafe, UserSafeIdx},
+    model::{
+        Game, GameId, GameSettings, GameStatus, GameSummary, LobbyFilter, SeatedUser, UndoRequest,
+        UserId, UserSafeIdx,
+    },
 };
 
 #[derive(Debug)]
@@ -13,25 +18,86 @@ impl GameRepo {
         Self { pool }
     }
 
-    pub async fn get_all(&self) -> Result<Vec<Game>, DbError> {
+    /// A page of the lobby as `user_id` sees it, newest games first. The lobby holds the public
+    /// games and the private ones they created or sit in.
+    pub async fn get_all(
+        &self,
+        user_id: UserId,
+        filter: &LobbyFilter,
+    ) -> Result<Vec<GameSummary>, DbError> {
+        // The page is picked from `games` and the user's seats alone, and only the games on it
+        // get their players looked up
         let rec = sqlx::query_as!(
-            Game,
-            "SELECT id, state as \"state: _\", created_by FROM games",
+            GameSummary,
+            r#"
+            SELECT   page.id AS "id!", creator.username AS creator, page.status AS "status!: _",
+                     page.settings AS "settings: Json<GameSettings>",
+                     seated.players AS "players!: Json<Vec<SeatedUser>>",
+                     page.free_seats::bigint AS "free_seats!", page.private AS "private!",
+                     EXTRACT(EPOCH FROM page.created_at)::bigint AS "created_at!"
+            FROM (
+                SELECT   games.id, games.status, games.settings, games.free_seats, games.private,
+                         games.created_at, games.created_by
+                FROM     games
+                WHERE    (NOT games.private OR games.created_by = $1
+                          OR games.id IN (SELECT game_id FROM joined WHERE user_id = $1))
+                AND      ($2::game_status IS NULL OR games.status = $2)
+                AND      ($3::smallint IS NULL OR games.seats = $3)
+                AND      ($4::boolean IS NULL OR games.ranked = $4)
+                AND      ($5::bigint IS NULL OR games.free_seats >= $5)
+                AND      (NOT $6 OR games.id IN (SELECT game_id FROM joined WHERE user_id = $1))
+                AND      ($9::uuid IS NULL OR games.id = $9)
+                ORDER BY games.created_at DESC, games.id
+                LIMIT    $7
+                OFFSET   $8
+            ) AS page
+            JOIN     users AS creator ON creator.id = page.created_by
+            CROSS JOIN LATERAL (
+                SELECT COALESCE(
+                           jsonb_agg(jsonb_build_object('idx', joined.idx, 'username', users.username)
+                                     ORDER BY joined.idx),
+                           '[]'
+                       ) AS players
+                FROM   joined
+                JOIN   users ON users.id = joined.user_id
+                WHERE  joined.game_id = page.id
+            ) AS seated
+            ORDER BY page.created_at DESC, page.id
+            "#,
+            user_id,
+            filter.status as _,
+            filter.seats,
+            filter.ranked,
+            filter.free_seats,
+            filter.mine,
+            filter.limit(),
+            filter.offset,
+            filter.game
         )
         .fetch_all(&self.pool)
-        .await
-        .map_err(|e| match e {
-            sqlx::error::Error::RowNotFound => DbError::NotFound(e),
-            _ => e.into(),
-        })?;
+        .await?;
 
         Ok(rec)
     }
 
+    /// What the lobby shows of `game`, whoever may see it.
+    pub async fn get_summary(&self, game: &Game) -> Result<Option<GameSummary>, DbError> {
+        let filter = LobbyFilter {
+            game: Some(game.id),
+            ..Default::default()
+        };
+
+        // The creator sees their game in the lobby even when it is private
+        Ok(self.get_all(game.created_by, &filter).await?.pop())
+    }
+
     pub async fn get_by_id(&self, id: GameId) -> Result<Game, DbError> {
         let rec = sqlx::query_as!(
             Game,
-            "SELECT id, state as \"state: _\", created_by FROM games WHERE id = $1",
+            "SELECT id, status as \"status: _\", settings as \"settings: Json<GameSettings>\",
+                state as \"state: _\", record as \"record: _\", resigned, private, created_by,
+                version
+            FROM games WHERE id = $1",
             id
         )
         .fetch_one(&self.pool)
@@ -44,15 +110,169 @@ impl GameRepo {
         Ok(rec)
     }
 
-    pub async fn update(&self, game: &Game) -> anyhow::Result<(), DbError> {
-        sqlx::query!(
+    /// Saves a new state of the game, worked out from the one at `game.version`. Any change to
+    /// the game drops a pending undo request, which was asked for the state before it. Returns
+    /// whether that dropped one, or `None` when nothing was saved because the game stopped being
+    /// played or another state was saved first.
+    pub async fn update(&self, game: &Game) -> Result<Option<bool>, DbError> {
+        let dropped_undo = sqlx::query_scalar!(
             r#"
             UPDATE games
-            SET state = $2
-            WHERE id = $1
+            SET state = $2, record = $3, undo = NULL, version = games.version + 1
+            FROM (SELECT id, undo FROM games WHERE id = $1 FOR UPDATE) AS old
+            WHERE games.id = old.id AND games.status = 'InProgress' AND games.version = $4
+            RETURNING old.undo IS NOT NULL AS "dropped_undo!"
             "#,
             game.id,
-            serde_json::to_value(&game.state)?
+            game.state.as_ref().map(serde_json::to_value).transpose()?,
+            game.record.as_ref().map(serde_json::to_value).transpose()?,
+            game.version
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(dropped_undo)
+    }
+
+    /// Saves the first deal and puts the game in progress, unless it already left the waiting
+    /// room. Returns whether this call started it. With `seating` the players are seated anew,
+    /// its first user in seat 0 and so on.
+    pub async fn start(&self, game: &Game, seating: Option<&[UserId]>) -> Result<bool, DbError> {
+        let mut tx = self.pool.begin().await?;
+
+        let result = sqlx::query!(
+            r#"
+            UPDATE games
+            SET status = 'InProgress', state = $2, record = $3
+            WHERE id = $1 AND status = 'Waiting'
+            "#,
+            game.id,
+            game.state.as_ref().map(serde_json::to_value).transpose()?,
+            game.record.as_ref().map(serde_json::to_value).transpose()?
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        if result.rows_affected() != 1 {
+            return Ok(false);
+        }
+
+        if let Some(seating) = seating {
+            // Everyone is ready by now, so the ready flags of the seats still hold
+            sqlx::query!(
+                "UPDATE joined SET user_id = NULL, swap_to = NULL WHERE game_id = $1",
+                game.id
+            )
+            .execute(&mut *tx)
+            .await?;
+
+            sqlx::query!(
+                r#"
+                UPDATE joined
+                SET    user_id = seating.user_id
+                FROM   UNNEST($2::uuid[]) WITH ORDINALITY AS seating(user_id, n)
+                WHERE  joined.game_id = $1
+                AND    joined.idx     = seating.n - 1
+                "#,
+                game.id,
+                seating
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    /// Moves the game from status `from` to `to`, returning false when it wasn't in `from`.
+    pub async fn set_status(
+        &self,
+        game_id: GameId,
+        from: GameStatus,
+        to: GameStatus,
+    ) -> Result<bool, DbError> {
+        let result = sqlx::query!(
+            "UPDATE games SET status = $3 WHERE id = $1 AND status = $2",
+            game_id,
+            from as _,
+            to as _
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Ends a game being played with `seat` resigning. Returns false when it wasn't being played.
+    pub async fn resign(&self, game_id: GameId, seat: i16) -> Result<bool, DbError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE games
+            SET status = 'Finished', resigned = $2
+            WHERE id = $1 AND status IN ('InProgress', 'Paused')
+            "#,
+            game_id,
+            seat
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    pub async fn get_undo(&self, game_id: GameId) -> Result<Option<UndoRequest>, DbError> {
+        let undo = sqlx::query_scalar!(
+            r#"SELECT undo as "undo: Json<UndoRequest>" FROM games WHERE id = $1"#,
+            game_id
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::error::Error::RowNotFound => DbError::NotFound(e),
+            _ => e.into(),
+        })?;
+
+        Ok(undo.map(|Json(undo)| undo))
+    }
+
+    /// Adds `seat` to the approvals of the pending undo, unless it lapsed by `now`, was asked
+    /// for by `seat` or already has their approval. Returns the request as it now stands.
+    pub async fn approve_undo(
+        &self,
+        game_id: GameId,
+        seat: usize,
+        now: i64,
+    ) -> Result<Option<UndoRequest>, DbError> {
+        let undo = sqlx::query_scalar!(
+            r#"
+            UPDATE games
+            SET    undo = jsonb_set(undo, '{approved}', (undo->'approved') || to_jsonb($2::int))
+            WHERE  id = $1
+            AND    (undo->>'expires_at')::bigint > $3
+            AND    (undo->>'seat')::int <> $2
+            AND    NOT (undo->'approved' @> jsonb_build_array($2::int))
+            RETURNING undo as "undo!: Json<UndoRequest>"
+            "#,
+            game_id,
+            seat as i32,
+            now
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(undo.map(|Json(undo)| undo))
+    }
+
+    pub async fn set_undo(
+        &self,
+        game_id: GameId,
+        undo: Option<&UndoRequest>,
+    ) -> Result<(), DbError> {
+        sqlx::query!(
+            "UPDATE games SET undo = $2 WHERE id = $1",
+            game_id,
+            undo.map(Json) as _
         )
         .execute(&self.pool)
         .await?;
@@ -60,16 +280,37 @@ impl GameRepo {
         Ok(())
     }
 
+    /// Number of the last event published for the game.
+    pub async fn get_seq(&self, game_id: GameId) -> Result<i64, DbError> {
+        let seq = sqlx::query_scalar!("SELECT seq FROM games WHERE id = $1", game_id)
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| match e {
+                sqlx::error::Error::RowNotFound => DbError::NotFound(e),
+                _ => e.into(),
+            })?;
+
+        Ok(seq)
+    }
+
     pub async fn create(&self, game: Game) -> anyhow::Result<(), DbError> {
-        const PLAYER_COUNT: i32 = 3;
+        let seats = game.rules().seats as i32;
         let mut tx = self.pool.begin().await?;
         sqlx::query!(
             r#"
-            INSERT INTO games (id, state, created_by)
-            VALUES ($1, $2, $3)
+            INSERT INTO games
+                (id, status, settings, state, record, private, seats, free_seats, ranked,
+                 created_by)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $7, $8, $9)
             "#,
             game.id,
-            serde_json::to_value(&game.state)?,
+            game.status as _,
+            game.settings.as_ref() as _,
+            game.state.as_ref().map(serde_json::to_value).transpose()?,
+            game.record.as_ref().map(serde_json::to_value).transpose()?,
+            game.private,
+            seats as i16,
+            game.rules().ranked,
             game.created_by
         )
         .execute(&mut *tx)
@@ -81,7 +322,7 @@ impl GameRepo {
             SELECT $1, generate_series(0, $2::int - 1)
             "#,
             game.id,
-            PLAYER_COUNT
+            seats
         )
         .execute(&mut *tx)
         .await?;
@@ -90,7 +331,19 @@ impl GameRepo {
         Ok(())
     }
 
-    pub async fn join(&self, game_id: GameId, user_id: UserId) -> Result<(), DbError> {
+    /// Takes `seat`, or without one the seat reserved for the user or else the lowest free
+    /// seat. Seats reserved for someone else are never taken, and without an invite only a seat
+    /// reserved for the user is. Returns the seat taken.
+    pub async fn join(
+        &self,
+        game_id: GameId,
+        user_id: UserId,
+        seat: Option<i16>,
+        invited: bool,
+    ) -> Result<i16, DbError> {
+        let mut tx = self.pool.begin().await?;
+        Self::lock_seats(&mut tx, game_id).await?;
+
         let opt_idx: Option<i16> = sqlx::query_scalar!(
             r#"
             WITH sel AS (
@@ -98,7 +351,9 @@ impl GameRepo {
                 FROM     joined
                 WHERE    game_id  = $1
                 AND      user_id IS NULL
-                ORDER BY idx
+                AND      ($3::smallint IS NULL OR idx = $3)
+                AND      (reserved_for = $2 OR ($4 AND reserved_for IS NULL))
+                ORDER BY reserved_for IS NOT DISTINCT FROM $2 DESC, idx
                 LIMIT    1
             )
             UPDATE    joined
@@ -109,26 +364,351 @@ impl GameRepo {
             RETURNING joined.idx;
             "#,
             game_id,
+            user_id,
+            seat,
+            invited
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let seat = opt_idx.ok_or(DbError::NoAvailableSlot)?;
+        Self::count_free_seats(&mut tx, game_id).await?;
+
+        tx.commit().await?;
+        Ok(seat)
+    }
+
+    /// Frees the seat of `user_id`. Returns false when they weren't seated.
+    pub async fn leave(&self, game_id: GameId, user_id: UserId) -> Result<bool, DbError> {
+        let mut tx = self.pool.begin().await?;
+        Self::lock_seats(&mut tx, game_id).await?;
+
+        let seat = sqlx::query_scalar!(
+            r#"
+            UPDATE    joined
+            SET       user_id = NULL, ready = FALSE, abort_vote = FALSE, swap_to = NULL
+            WHERE     game_id = $1 AND user_id = $2
+            RETURNING idx
+            "#,
+            game_id,
             user_id
         )
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *tx)
         .await?;
 
-        opt_idx.ok_or(DbError::NoAvailableSlot)?;
+        let Some(seat) = seat else {
+            return Ok(false);
+        };
+        Self::drop_swaps_to(&mut tx, game_id, &[seat]).await?;
+        Self::count_free_seats(&mut tx, game_id).await?;
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    /// Moves `user_id` to the free seat `to`. Returns false when it was taken in the meantime.
+    pub async fn move_seat(
+        &self,
+        game_id: GameId,
+        user_id: UserId,
+        to: i16,
+    ) -> Result<bool, DbError> {
+        let mut tx = self.pool.begin().await?;
+        Self::lock_seats(&mut tx, game_id).await?;
+
+        let from = sqlx::query_scalar!(
+            r#"
+            UPDATE    joined
+            SET       user_id = NULL, ready = FALSE, swap_to = NULL
+            WHERE     game_id = $1 AND user_id = $2
+            RETURNING idx
+            "#,
+            game_id,
+            user_id
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some(from) = from else {
+            return Ok(false);
+        };
+
+        let result = sqlx::query!(
+            r#"
+            UPDATE joined
+            SET    user_id = $2, ready = FALSE, abort_vote = FALSE
+            WHERE  game_id = $1 AND idx = $3 AND user_id IS NULL
+            AND    (reserved_for IS NULL OR reserved_for = $2)
+            "#,
+            game_id,
+            user_id,
+            to
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        if result.rows_affected() != 1 {
+            return Ok(false);
+        }
+        Self::drop_swaps_to(&mut tx, game_id, &[from, to]).await?;
+        Self::count_free_seats(&mut tx, game_id).await?;
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    /// Swaps the seats of `a` and `b`, given as the user and the seat they sit in. Returns false
+    /// when either moved in the meantime. Both have to say they're ready again.
+    pub async fn swap_seats(
+        &self,
+        game_id: GameId,
+        a: (UserId, i16),
+        b: (UserId, i16),
+    ) -> Result<bool, DbError> {
+        let mut tx = self.pool.begin().await?;
+
+        // Emptied first, as a user can't sit in two seats even for a moment
+        let result = sqlx::query!(
+            r#"
+            UPDATE joined
+            SET    user_id = NULL, ready = FALSE, swap_to = NULL
+            WHERE  game_id = $1
+            AND    ((idx = $3 AND user_id = $2) OR (idx = $5 AND user_id = $4))
+            "#,
+            game_id,
+            a.0,
+            a.1,
+            b.0,
+            b.1
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        if result.rows_affected() != 2 {
+            return Ok(false);
+        }
+
+        sqlx::query!(
+            r#"
+            UPDATE joined
+            SET    user_id = CASE idx WHEN $3 THEN $4::uuid ELSE $2::uuid END
+            WHERE  game_id = $1 AND idx IN ($3, $5)
+            "#,
+            game_id,
+            a.0,
+            a.1,
+            b.0,
+            b.1
+        )
+        .execute(&mut *tx)
+        .await?;
+        Self::drop_swaps_to(&mut tx, game_id, &[a.1, b.1]).await?;
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    pub async fn get_invite_code(&self, game_id: GameId) -> Result<Option<String>, DbError> {
+        let code = sqlx::query_scalar!("SELECT invite_code FROM games WHERE id = $1", game_id)
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| match e {
+                sqlx::error::Error::RowNotFound => DbError::NotFound(e),
+                _ => e.into(),
+            })?;
+
+        Ok(code)
+    }
+
+    /// Replaces the invite code of a game, `None` revoking it.
+    pub async fn set_invite_code(
+        &self,
+        game_id: GameId,
+        code: Option<&str>,
+    ) -> Result<(), DbError> {
+        sqlx::query!(
+            "UPDATE games SET invite_code = $2 WHERE id = $1",
+            game_id,
+            code
+        )
+        .execute(&self.pool)
+        .await?;
 
         Ok(())
     }
 
+    /// Keeps the free `seat` for `user_id`, or frees it up for anyone with `None`. Returns false
+    /// when someone is sitting there.
+    pub async fn reserve(
+        &self,
+        game_id: GameId,
+        seat: i16,
+        user_id: Option<UserId>,
+    ) -> Result<bool, DbError> {
+        let mut tx = self.pool.begin().await?;
+        Self::lock_seats(&mut tx, game_id).await?;
+
+        let result = sqlx::query!(
+            r#"
+            UPDATE joined
+            SET    reserved_for = $3
+            WHERE  game_id = $1 AND idx = $2 AND user_id IS NULL
+            "#,
+            game_id,
+            seat,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        if result.rows_affected() != 1 {
+            return Ok(false);
+        }
+        Self::count_free_seats(&mut tx, game_id).await?;
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    /// Asks to swap into seat `to`, or takes the ask back with `None`.
+    pub async fn set_swap_to(
+        &self,
+        game_id: GameId,
+        user_id: UserId,
+        to: Option<i16>,
+    ) -> Result<(), DbError> {
+        sqlx::query!(
+            "UPDATE joined SET swap_to = $3 WHERE game_id = $1 AND user_id = $2",
+            game_id,
+            user_id,
+            to
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Drops asks to swap into `seats`, whose players changed.
+    async fn drop_swaps_to(
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        game_id: GameId,
+        seats: &[i16],
+    ) -> Result<(), DbError> {
+        sqlx::query!(
+            "UPDATE joined SET swap_to = NULL WHERE game_id = $1 AND swap_to = ANY($2)",
+            game_id,
+            seats
+        )
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Holds off other changes to the seats of the game until `tx` ends, so that what
+    /// `count_free_seats` counts is what's committed.
+    async fn lock_seats(
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        game_id: GameId,
+    ) -> Result<(), DbError> {
+        sqlx::query!("SELECT id FROM games WHERE id = $1 FOR UPDATE", game_id)
+            .fetch_optional(&mut **tx)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Counts again the seats the lobby shows as free, which leaves out the ones kept for
+    /// someone. Needs the seats locked with `lock_seats`.
+    async fn count_free_seats(
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        game_id: GameId,
+    ) -> Result<(), DbError> {
+        sqlx::query!(
+            r#"
+            UPDATE games
+            SET    free_seats = (
+                SELECT COUNT(*) FROM joined
+                WHERE  game_id = $1 AND user_id IS NULL AND reserved_for IS NULL
+            )
+            WHERE  id = $1
+            "#,
+            game_id
+        )
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Marks a seated player ready to start, or not.
+    pub async fn set_ready(
+        &self,
+        game_id: GameId,
+        user_id: UserId,
+        ready: bool,
+    ) -> Result<(), DbError> {
+        sqlx::query!(
+            "UPDATE joined SET ready = $3 WHERE game_id = $1 AND user_id = $2",
+            game_id,
+            user_id,
+            ready
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn set_abort_vote(
+        &self,
+        game_id: GameId,
+        user_id: UserId,
+        vote: bool,
+    ) -> Result<(), DbError> {
+        sqlx::query!(
+            "UPDATE joined SET abort_vote = $3 WHERE game_id = $1 AND user_id = $2",
+            game_id,
+            user_id,
+            vote
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Whether the user sits in the game or has a seat kept for them.
+    pub async fn is_expected(&self, game_id: GameId, user_id: UserId) -> Result<bool, DbError> {
+        let expected = sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM joined
+                WHERE  game_id = $1 AND (user_id = $2 OR reserved_for = $2)
+            ) AS "expected!"
+            "#,
+            game_id,
+            user_id
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(expected)
+    }
+
     pub async fn get_joined_by_game_id(
         &self,
         game_id: GameId,
     ) -> Result<Vec<UserSafeIdx>, DbError> {
         let rec = sqlx::query_as!(
             UserSafeIdx,
-            "SELECT users.id, users.username, joined.idx
+            "SELECT users.id, users.username, joined.idx, joined.ready, joined.abort_vote,
+                joined.swap_to
             FROM users
             JOIN joined ON users.id = joined.user_id
-            WHERE joined.game_id = $1",
+            WHERE joined.game_id = $1
+            ORDER BY joined.idx",
             game_id
         )
         .fetch_all(&self.pool)
@@ -137,3 +717,197 @@ impl GameRepo {
         Ok(rec)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use prefsty::core::{game::new_game, ruleset::Ruleset};
+    use sqlx::postgres::PgPoolOptions;
+
+    use super::*;
+    use crate::http::repos::{model::User, user::UserRepo};
+
+    async fn pool() -> sqlx::PgPool {
+        let url = std::env::var("DATABASE_URL").unwrap();
+        PgPoolOptions::new().connect(&url).await.unwrap()
+    }
+
+    async fn user(pool: &sqlx::PgPool) -> UserId {
+        let id = UserId::new_v4();
+        let user = User {
+            id,
+            username: format!("test-{id}"),
+            password: String::new(),
+            email: format!("{id}@test"),
+        };
+        UserRepo::new(pool.clone()).create(user).await.unwrap();
+        id
+    }
+
+    async fn game(repo: &GameRepo, created_by: UserId, status: GameStatus) -> Game {
+        let game = Game {
+            id: GameId::new_v4(),
+            status,
+            settings: None,
+            state: Some(new_game(0, 30, 0, Ruleset::default())),
+            record: None,
+            resigned: None,
+            private: false,
+            created_by,
+            version: 0,
+        };
+        let id = game.id;
+        repo.create(game).await.unwrap();
+        repo.get_by_id(id).await.unwrap()
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Postgres at DATABASE_URL"]
+    async fn states_are_saved_over_the_one_they_came_from() {
+        let pool = pool().await;
+        let repo = GameRepo::new(pool.clone());
+        let game = game(&repo, user(&pool).await, GameStatus::InProgress).await;
+
+        // Two moves worked out from the same state: the second is refused
+        let first = repo.get_by_id(game.id).await.unwrap();
+        let second = repo.get_by_id(game.id).await.unwrap();
+        assert!(repo.update(&first).await.unwrap().is_some());
+        assert!(repo.update(&second).await.unwrap().is_none());
+
+        // A move drops the undo asked for before it
+        let undo = UndoRequest {
+            seat: 1,
+            approved: Vec::new(),
+            expires_at: i64::MAX,
+        };
+        repo.set_undo(game.id, Some(&undo)).await.unwrap();
+        let saved = repo.get_by_id(game.id).await.unwrap();
+        assert_eq!(saved.version, 1);
+        assert_eq!(repo.update(&saved).await.unwrap(), Some(true));
+        assert!(repo.get_undo(game.id).await.unwrap().is_none());
+
+        // Nor is one saved after the game was paused
+        let paused = repo.get_by_id(game.id).await.unwrap();
+        assert!(
+            repo.set_status(game.id, GameStatus::InProgress, GameStatus::Paused)
+                .await
+                .unwrap()
+        );
+        assert!(repo.update(&paused).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Postgres at DATABASE_URL"]
+    async fn players_and_reserved_users_are_expected() {
+        let pool = pool().await;
+        let repo = GameRepo::new(pool.clone());
+        let creator = user(&pool).await;
+        let game = game(&repo, creator, GameStatus::Waiting).await;
+        let (player, guest, stranger) = (user(&pool).await, user(&pool).await, user(&pool).await);
+
+        repo.join(game.id, player, None, true).await.unwrap();
+        assert!(repo.reserve(game.id, 2, Some(guest)).await.unwrap());
+
+        assert!(repo.is_expected(game.id, player).await.unwrap());
+        assert!(repo.is_expected(game.id, guest).await.unwrap());
+        assert!(!repo.is_expected(game.id, stranger).await.unwrap());
+    }
+
+    /// Who sits where, as (user, seat, ready, seat asked to swap into).
+    async fn seating(repo: &GameRepo, game_id: GameId) -> Vec<(UserId, i16, bool, Option<i16>)> {
+        let joined = repo.get_joined_by_game_id(game_id).await.unwrap();
+        joined
+            .into_iter()
+            .map(|user| (user.id, user.idx, user.ready, user.swap_to))
+            .collect()
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Postgres at DATABASE_URL"]
+    async fn seats_are_picked_swapped_and_left() {
+        let pool = pool().await;
+        let repo = GameRepo::new(pool.clone());
+        let game = game(&repo, user(&pool).await, GameStatus::Waiting).await;
+        let (a, b, c) = (user(&pool).await, user(&pool).await, user(&pool).await);
+
+        // A seat asked for is taken, and nobody else gets it
+        assert_eq!(repo.join(game.id, a, Some(1), true).await.unwrap(), 1);
+        assert!(matches!(
+            repo.join(game.id, b, Some(1), true).await,
+            Err(DbError::NoAvailableSlot)
+        ));
+        assert_eq!(repo.join(game.id, b, None, true).await.unwrap(), 0);
+
+        // Swapping makes both say they're ready again and settles the ask
+        repo.set_ready(game.id, a, true).await.unwrap();
+        repo.set_swap_to(game.id, b, Some(1)).await.unwrap();
+        assert!(repo.swap_seats(game.id, (a, 1), (b, 0)).await.unwrap());
+        let swapped = vec![(a, 0, false, None), (b, 1, false, None)];
+        assert_eq!(seating(&repo, game.id).await, swapped);
+
+        // Nothing changes when either has moved since
+        assert!(!repo.swap_seats(game.id, (a, 1), (b, 0)).await.unwrap());
+        assert!(!repo.move_seat(game.id, a, 1).await.unwrap());
+        assert_eq!(seating(&repo, game.id).await, swapped);
+
+        assert!(repo.move_seat(game.id, a, 2).await.unwrap());
+        assert_eq!(repo.join(game.id, c, None, true).await.unwrap(), 0);
+
+        // Leaving drops the asks to swap into the seat left
+        repo.set_swap_to(game.id, c, Some(2)).await.unwrap();
+        assert!(repo.leave(game.id, a).await.unwrap());
+        assert!(!repo.leave(game.id, a).await.unwrap());
+        assert_eq!(
+            seating(&repo, game.id).await,
+            vec![(c, 0, false, None), (b, 1, false, None)]
+        );
+    }
+
+    /// Free seats as kept for the lobby, and as counted from the seats.
+    async fn free_seats(pool: &sqlx::PgPool, game_id: GameId) -> (i64, i64) {
+        sqlx::query_as(
+            "SELECT free_seats::bigint, (
+                SELECT COUNT(*) FROM joined
+                WHERE  game_id = $1 AND user_id IS NULL AND reserved_for IS NULL
+            )
+            FROM games WHERE id = $1",
+        )
+        .bind(game_id)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Postgres at DATABASE_URL"]
+    async fn free_seats_leave_out_taken_and_reserved_ones() {
+        let pool = pool().await;
+        let repo = GameRepo::new(pool.clone());
+        let game = game(&repo, user(&pool).await, GameStatus::Waiting).await;
+        let (a, b, guest) = (user(&pool).await, user(&pool).await, user(&pool).await);
+        assert_eq!(free_seats(&pool, game.id).await, (3, 3));
+
+        assert!(repo.reserve(game.id, 2, Some(guest)).await.unwrap());
+        assert_eq!(free_seats(&pool, game.id).await, (2, 2));
+
+        // Joining at once, both seats are counted taken
+        let (joined_a, joined_b) = tokio::join!(
+            repo.join(game.id, a, None, true),
+            repo.join(game.id, b, None, true)
+        );
+        let seat_a = joined_a.unwrap();
+        joined_b.unwrap();
+        assert_eq!(free_seats(&pool, game.id).await, (0, 0));
+
+        // The seat kept for the guest stays out of the count when they take it and leave it
+        assert_eq!(repo.join(game.id, guest, None, false).await.unwrap(), 2);
+        assert!(repo.leave(game.id, guest).await.unwrap());
+        assert_eq!(free_seats(&pool, game.id).await, (0, 0));
+
+        assert!(repo.leave(game.id, a).await.unwrap());
+        assert!(repo.move_seat(game.id, b, seat_a).await.unwrap());
+        assert!(repo.reserve(game.id, 2, None).await.unwrap());
+        let (stored, counted) = free_seats(&pool, game.id).await;
+        assert_eq!(stored, counted);
+        assert_eq!(stored, 2);
+    }
+}
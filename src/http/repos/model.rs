@@ -1,14 +1,203 @@
-use prefsty::core::game::GameState;
+use prefsty::core::{game::GameState, record::GameRecord, ruleset::Ruleset};
 use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
 
 pub type UserId = uuid::Uuid;
 pub type GameId = uuid::Uuid;
+/// Every connection gets its own id, so a user can follow a game from several tabs or devices.
+pub type ConnectionId = uuid::Uuid;
+/// Server instance, so the instances sharing a database can tell their connections apart.
+pub type InstanceId = uuid::Uuid;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Game {
     pub id: GameId,
-    pub state: GameState,
+    pub status: GameStatus,
+    /// What the game was created with, kept to deal it once the table is ready.
+    pub settings: Option<Json<GameSettings>>,
+    /// Nothing is dealt while the game is waiting for players.
+    pub state: Option<GameState>,
+    pub record: Option<GameRecord>,
+    /// Seat of the player who resigned, ending the game.
+    pub resigned: Option<i16>,
+    /// Hidden from the lobby and joined by invite only.
+    pub private: bool,
     pub created_by: UserId,
+    /// Number of states saved since the game was dealt.
+    pub version: i32,
+}
+
+impl Game {
+    pub fn rules(&self) -> Ruleset {
+        match (&self.state, &self.settings) {
+            (Some(state), _) => *state.rules(),
+            (None, Some(settings)) => settings.rules,
+            (None, None) => Ruleset::default(),
+        }
+    }
+
+    pub fn spectators(&self) -> Spectators {
+        self.settings
+            .as_ref()
+            .map_or_else(Spectators::default, |settings| settings.spectators)
+    }
+}
+
+/// Where a game is in its life. Games wait for every seat to be taken and ready, are played,
+/// possibly with breaks, and end either played out or resigned (finished) or by everyone
+/// agreeing to stop (abandoned).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "game_status")]
+pub enum GameStatus {
+    Waiting,
+    InProgress,
+    Paused,
+    Finished,
+    Abandoned,
+}
+
+impl GameStatus {
+    pub fn is_over(&self) -> bool {
+        matches!(self, GameStatus::Finished | GameStatus::Abandoned)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameSettings {
+    pub first: usize,
+    pub starting_score: u32,
+    pub num_refas: usize,
+    #[serde(default)]
+    pub rules: Ruleset,
+    /// Seat the players in random order when the game starts.
+    #[serde(default)]
+    pub random_seats: bool,
+    #[serde(default)]
+    pub spectators: Spectators,
+}
+
+/// What people who don't sit at the table get to see of the game.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Spectators {
+    /// Only the players can follow the game.
+    Disallowed,
+    /// The table as the players see it, with only the hands lying open.
+    #[default]
+    Public,
+    /// Every hand, `delay` seconds late so it can't be passed on to a player. Casual games only.
+    AllHands { delay: u64 },
+}
+
+/// What the lobby shows of a game.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GameSummary {
+    pub id: GameId,
+    /// Username of the creator.
+    pub creator: String,
+    pub status: GameStatus,
+    pub settings: Option<Json<GameSettings>>,
+    pub players: Json<Vec<SeatedUser>>,
+    pub free_seats: i64,
+    pub private: bool,
+    /// Unix time the game was created at.
+    pub created_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SeatedUser {
+    pub idx: i16,
+    pub username: String,
+}
+
+/// Which games to list in the lobby, and which page of them.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LobbyFilter {
+    pub status: Option<GameStatus>,
+    /// Tables of 3 or of 4.
+    pub seats: Option<i16>,
+    pub ranked: Option<bool>,
+    /// At least this many seats free.
+    pub free_seats: Option<i64>,
+    /// Only games the user sits in.
+    pub mine: bool,
+    pub limit: Option<i64>,
+    pub offset: i64,
+    /// Only this game, for looking up a single summary.
+    #[serde(skip)]
+    pub game: Option<GameId>,
+}
+
+impl LobbyFilter {
+    const DEFAULT_LIMIT: i64 = 20;
+    const MAX_LIMIT: i64 = 100;
+
+    pub fn limit(&self) -> i64 {
+        self.limit
+            .unwrap_or(Self::DEFAULT_LIMIT)
+            .clamp(1, Self::MAX_LIMIT)
+    }
+}
+
+/// Players chat at the table, and people watching among themselves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "chat_channel")]
+pub enum ChatChannel {
+    /// Players' chat, which spectators can read too.
+    Table,
+    Spectators,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub id: i64,
+    pub user_id: UserId,
+    pub username: String,
+    pub channel: ChatChannel,
+    pub text: String,
+    /// Unix time the message was sent at.
+    pub sent_at: i64,
+}
+
+/// Someone connected to a game, on any instance.
+#[derive(Debug)]
+pub struct Connection {
+    pub user_id: UserId,
+    /// The user said they stepped away, e.g. by switching to another tab.
+    pub away: bool,
+}
+
+/// A request a client sent with an id, as far as it got.
+#[derive(Debug)]
+pub struct Request {
+    /// Handled, as opposed to still being handled or abandoned halfway.
+    pub done: bool,
+    /// Why it failed, if it did.
+    pub error: Option<String>,
+}
+
+/// Takeback a seat asked for, waiting on the other seated players.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UndoRequest {
+    pub seat: usize,
+    /// Seats that agreed so far.
+    pub approved: Vec<usize>,
+    /// Unix time the request lapses at.
+    pub expires_at: i64,
+}
+
+impl UndoRequest {
+    pub fn is_expired(&self, now: i64) -> bool {
+        now >= self.expires_at
+    }
+
+    /// Every seated player but the one asking has agreed.
+    pub fn is_approved(&self, joined: &[UserSafeIdx]) -> bool {
+        joined
+            .iter()
+            .map(|user| user.idx as usize)
+            .all(|seat| seat == self.seat || self.approved.contains(&seat))
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -30,4 +219,9 @@ pub struct UserSafeIdx {
     pub id: UserId,
     pub username: String,
     pub idx: i16,
+    pub ready: bool,
+    /// Voted to abandon the game.
+    pub abort_vote: bool,
+    /// Seat they asked to swap into.
+    pub swap_to: Option<i16>,
 }
@@ -0,0 +1,89 @@
+use crate::http::repos::{
+    error::DbError,
+    model::{Request, UserId},
+};
+
+/// Requests clients sent with an id, so that repeats of them can be told apart on any instance.
+#[derive(Debug)]
+pub struct RequestRepo {
+    pool: sqlx::PgPool,
+}
+
+impl RequestRepo {
+    pub fn new(pool: sqlx::PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Remembers a request of `user_id`, unless one with the same id came in over the last
+    /// `seconds`, or was claimed over the last `timeout` seconds and is still being handled.
+    /// Returns whether it's the first.
+    pub async fn claim(
+        &self,
+        user_id: UserId,
+        id: &str,
+        seconds: f64,
+        timeout: f64,
+    ) -> Result<bool, DbError> {
+        let claimed = sqlx::query!(
+            r#"
+            INSERT INTO requests (user_id, id) VALUES ($1, $2)
+            ON CONFLICT (user_id, id) DO UPDATE SET done = FALSE, error = NULL, created_at = now()
+            WHERE requests.created_at < now() - make_interval(secs => $3)
+                OR (NOT requests.done AND requests.created_at < now() - make_interval(secs => $4))
+            RETURNING id
+            "#,
+            user_id,
+            id,
+            seconds,
+            timeout
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(claimed.is_some())
+    }
+
+    pub async fn get(&self, user_id: UserId, id: &str) -> Result<Option<Request>, DbError> {
+        let request = sqlx::query_as!(
+            Request,
+            "SELECT done, error FROM requests WHERE user_id = $1 AND id = $2",
+            user_id,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(request)
+    }
+
+    /// Records that a request was handled, and why it failed if it did.
+    pub async fn settle(
+        &self,
+        user_id: UserId,
+        id: &str,
+        error: Option<&str>,
+    ) -> Result<(), DbError> {
+        sqlx::query!(
+            "UPDATE requests SET done = TRUE, error = $3 WHERE user_id = $1 AND id = $2",
+            user_id,
+            id,
+            error
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Forgets requests that came in more than `seconds` ago, which can't be repeated anymore.
+    pub async fn remove_expired(&self, seconds: f64) -> Result<(), DbError> {
+        sqlx::query!(
+            "DELETE FROM requests WHERE created_at < now() - make_interval(secs => $1)",
+            seconds
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -3,7 +3,7 @@ use axum::{
     routing::{any, get, post},
 };
 
-use crate::http::{ApiContext, controllers, ws};
+use crate::http::{ApiContext, controllers, lobby, outbox, ws};
 use tower::ServiceBuilder;
 use tower_http::trace::TraceLayer;
 
@@ -20,11 +20,33 @@ pub async fn app(api_context: ApiContext) -> Router {
             get(controllers::game::get_joined_by_game_id),
         )
         .route("/games/{id}/join", post(controllers::game::join))
+        .route(
+            "/games/{id}/invite",
+            post(controllers::game::invite).delete(controllers::game::revoke_invite),
+        )
+        .route("/games/{id}/reserve", post(controllers::game::reserve))
+        .route("/games/{id}/leave", post(controllers::game::leave))
+        .route("/games/{id}/seat", post(controllers::game::change_seat))
+        .route("/games/{id}/ready", post(controllers::game::ready))
+        .route("/games/{id}/pause", post(controllers::game::pause))
+        .route("/games/{id}/resume", post(controllers::game::resume))
+        .route("/games/{id}/abort", post(controllers::game::vote_abort))
+        .route("/games/{id}/resign", post(controllers::game::resign))
+        .route("/games/{id}/record", get(controllers::game::export_record))
+        .route(
+            "/games/{id}/score-sheet",
+            get(controllers::game::score_sheet),
+        )
+        .route("/games/{id}/settlement", get(controllers::game::settlement))
+        .route("/games/import", post(controllers::game::import_record))
         .route("/games", post(controllers::game::create));
 
-    let ws_route = Router::new().route("/ws/{game_id}", any(ws::handler));
+    let ws_route = Router::new()
+        .route("/ws/lobby", any(lobby::handler))
+        .route("/ws/{game_id}", any(ws::handler));
 
     Router::new()
+        .route("/metrics", get(outbox::metrics))
         .merge(auth_routes)
         .merge(games_routes)
         .merge(ws_route)
@@ -1,17 +1,27 @@
 use crate::http::{
     ApiContext,
+    controllers::{self, game::InviteKey},
+    error::AppError,
     extractors::AuthUser,
+    fanout::{self, Audience, Event},
+    lobby::{self, LobbyEventKind},
+    outbox::{self, Outbox, OutboxReceiver},
     repos::{
+        error::DbError,
         game::GameRepo,
         model,
-        model::{GameId, UserId, UserSafeIdx},
+        model::{
+            ChatChannel, ChatMessage, Connection, ConnectionId, GameId, GameStatus, Spectators,
+            UndoRequest, UserId, UserSafeIdx,
+        },
     },
 };
 use axum::{
     extract::{
-        Path, State, WebSocketUpgrade,
+        Path, Query, State, WebSocketUpgrade,
         ws::{Message, Utf8Bytes, WebSocket},
     },
+    http::StatusCode,
     response::Response,
 };
 use dashmap::DashMap;
@@ -21,76 +31,165 @@ use futures_util::{
 };
 use prefsty::core::{
     actions::{GameAction, GameActionKind},
+    all_pass::AllPassState,
     bidding::{
         bidding::BiddingState,
         no_bid::{NoBidChoiceState, NoBidClaimState},
     },
+    card_set::CardSet,
     choosing::{
         ChoosingCardsState, ChoosingContractState, ContreDeclaredState,
         HelpOrContreToContractState, RespondingToContractState,
     },
     game::{Game, GameState, PlayerScore, Refas},
     playing::PlayingState,
-    types::Card,
+    ruleset::{HAND_PLAYERS, Ruleset},
 };
 use serde::{Deserialize, Serialize};
-use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel};
+use std::{collections::HashSet, time::Duration};
+use time::OffsetDateTime;
 
-pub type ClientMap = DashMap<(GameId, UserId), UnboundedSender<Message>>;
+/// Connections to games on this instance, the ones on others being in `ConnectionRepo`.
+pub type ClientMap = DashMap<ConnectionId, Client>;
+
+/// A connection to a game.
+#[derive(Debug)]
+pub struct Client {
+    game_id: GameId,
+    user_id: UserId,
+    tx: Outbox,
+    /// Number of the last event of the game the connection was sent, or was past when it
+    /// connected.
+    seq: i64,
+    /// Waiting for a reconnecting client to say where it left off, events being held back until
+    /// then.
+    held: bool,
+}
+
+/// Whether a seated player is there to play.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Presence {
+    Online,
+    Away,
+    Offline,
+}
+
+/// How often connections are pinged.
+pub(crate) const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+/// Connections that haven't sent anything, pongs included, for this long are dropped.
+pub(crate) const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(45);
+/// How long a new connection has to resume before it's caught up from when it connected.
+const RESUME_GRACE: Duration = Duration::from_secs(2);
+/// How long a request id is remembered, repeats of it being answered without handling it again.
+pub(crate) const REQUEST_WINDOW: Duration = Duration::from_secs(10 * 60);
+/// How long a request id stays claimed without an outcome, after which the instance handling it
+/// is taken to be gone and a retry is handled instead.
+const CLAIM_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_REQUEST_ID_LEN: usize = 64;
 
 pub async fn handler(
     ws: WebSocketUpgrade,
     user: AuthUser,
     Path(game_id): Path<GameId>,
+    Query(invite): Query<InviteKey>,
     State(state): State<ApiContext>,
-) -> Response {
-    ws.on_upgrade(move |socket| handle_socket(socket, user, game_id, state))
+) -> Result<Response, AppError> {
+    let game = controllers::game::get_visible(&state, game_id, &user, &invite).await?;
+    if game.spectators() == Spectators::Disallowed {
+        let joined = state.game_repo.get_joined_by_game_id(game_id).await?;
+        if seat_of(&joined, user.user_id).is_err() {
+            return Err(AppError::new(
+                StatusCode::FORBIDDEN,
+                "game doesn't allow spectators",
+            ));
+        }
+    }
+
+    Ok(ws.on_upgrade(move |socket| handle_socket(socket, user, game_id, state)))
 }
 
 async fn handle_socket(socket: WebSocket, user: AuthUser, game_id: GameId, state: ApiContext) {
     let (ws_tx, ws_rx) = socket.split();
-    let (tx, rx) = unbounded_channel::<Message>();
+    let (tx, rx) = outbox::channel(&state.metrics);
     let user_id = user.user_id;
-    state.clients.insert((game_id, user_id), tx.clone());
+    let connection = ConnectionId::new_v4();
+    let Ok(seq) = state.game_repo.get_seq(game_id).await else {
+        return;
+    };
+    let client = Client {
+        game_id,
+        user_id,
+        tx: tx.clone(),
+        seq,
+        held: true,
+    };
+    state.clients.insert(connection, client);
+    let connection_repo = &state.connection_repo;
+    if connection_repo
+        .add(connection, state.instance, game_id, user_id)
+        .await
+        .is_err()
+    {
+        state.clients.remove(&connection);
+        return;
+    }
+
+    let _ = broadcast_spectators(&state, game_id).await;
+    let _ = broadcast_presence(&state, game_id, user_id).await;
+
+    // clients that don't resume get the chat and what happened since they connected
+    {
+        let state = state.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(RESUME_GRACE).await;
+            let _ = catch_up(&state, connection, game_id, None).await;
+        });
+    }
 
-    let write_task = tokio::spawn(write(ws_tx, rx));
+    let mut write_task = tokio::spawn(write(ws_tx, rx));
 
-    let read_task = {
+    let mut read_task = {
         let state = state.clone();
-        tokio::spawn(read(ws_rx, tx, game_id, user_id, state))
+        tokio::spawn(read(ws_rx, connection, game_id, user_id, state))
     };
 
+    // a connection that fell behind is closed by the writer, which takes the reader with it
     tokio::select! {
-        _ = write_task => {},
-        _ = read_task => {},
+        _ = &mut write_task => read_task.abort(),
+        _ = &mut read_task => write_task.abort(),
     }
 
-    state.clients.remove(&(game_id, user_id));
+    state.clients.remove(&connection);
+    let _ = state.connection_repo.remove(connection).await;
+    let _ = broadcast_spectators(&state, game_id).await;
+    let _ = broadcast_presence(&state, game_id, user_id).await;
 }
 
 async fn read(
     mut ws_rx: SplitStream<WebSocket>,
-    tx: UnboundedSender<Message>,
+    connection: ConnectionId,
     game_id: GameId,
     user_id: UserId,
     state: ApiContext,
 ) {
-    while let Some(msg) = ws_rx.next().await {
-        let msg_bytes = if let Ok(Message::Text(bytes)) = msg {
-            bytes
-        } else {
-            // client disconnected
-            return;
+    loop {
+        let msg = match tokio::time::timeout(HEARTBEAT_TIMEOUT, ws_rx.next()).await {
+            Ok(Some(Ok(msg))) => msg,
+            // client disconnected, or went quiet and missed the heartbeat
+            _ => return,
         };
 
-        // process and serialize response
-        if let Err(err) = handle_message(msg_bytes, user_id, game_id, &state).await {
-            let err_msg = serde_json::to_string(&OutgoingMessage {
-                kind: OutgoingMessageKind::Error(err.to_string()),
-            })
-            .unwrap();
+        let msg_bytes = match msg {
+            Message::Text(bytes) => bytes,
+            Message::Close(_) => return,
+            // pongs answering the heartbeat, which only show the client is still there
+            _ => continue,
+        };
 
-            if tx.send(err_msg.into()).is_err() {
+        // process and serialize response
+        if let Err(err) = handle_message(msg_bytes, connection, user_id, game_id, &state).await {
+            let kind = OutgoingMessageKind::Error(err.to_string());
+            if !send_direct(&state, connection, kind) {
                 // client disconnected
                 return;
             }
@@ -98,8 +197,18 @@ async fn read(
     }
 }
 
-async fn write(mut ws_tx: SplitSink<WebSocket, Message>, mut rx: UnboundedReceiver<Message>) {
-    while let Some(msg) = rx.recv().await {
+async fn write(mut ws_tx: SplitSink<WebSocket, Message>, mut rx: OutboxReceiver) {
+    let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+
+    loop {
+        let msg = tokio::select! {
+            msg = rx.recv() => match msg {
+                Some(msg) => msg,
+                None => break,
+            },
+            _ = heartbeat.tick() => Message::Ping(Default::default()),
+        };
+
         if ws_tx.send(msg).await.is_err() {
             // sink closed, stop writing
             break;
@@ -109,6 +218,10 @@ async fn write(mut ws_tx: SplitSink<WebSocket, Message>, mut rx: UnboundedReceiv
 
 #[derive(Serialize, Deserialize)]
 struct IncomingMessage {
+    /// Picked by the client, unique among its user's requests, to be told whether this one
+    /// went through and to retry it safely.
+    #[serde(default)]
+    id: Option<String>,
     kind: IncomingMessageKind,
 }
 
@@ -116,11 +229,44 @@ struct IncomingMessage {
 enum IncomingMessageKind {
     Game(GameActionKind),
     Sync,
+    /// Sent first by a reconnecting client, with the number of the last message it got, to be
+    /// sent what it missed.
+    Resume {
+        last_seq: i64,
+    },
+    /// Asks to take back your last action.
+    RequestUndo,
+    ApproveUndo,
+    DeclineUndo,
+    /// Says something at the table, or among spectators when not seated.
+    Chat(String),
+    /// Stops showing a user's chat to you, everywhere.
+    Mute(UserId),
+    Unmute(UserId),
+    /// Tells the table you stepped away, or came back with `false`.
+    Away(bool),
+}
+
+impl IncomingMessageKind {
+    /// Whether doing it again leaves things as they are, or only touches the connection it came
+    /// on, so that repeats of it are handled rather than deduplicated.
+    fn is_repeatable(&self) -> bool {
+        matches!(
+            self,
+            IncomingMessageKind::Sync
+                | IncomingMessageKind::Resume { .. }
+                | IncomingMessageKind::Mute(_)
+                | IncomingMessageKind::Unmute(_)
+                | IncomingMessageKind::Away(_)
+        )
+    }
 }
 
+/// Numbered by the last event of the game it comes after, which clients resume from.
 #[derive(Serialize)]
-struct OutgoingMessage<'a> {
-    kind: OutgoingMessageKind<'a>,
+struct OutgoingMessage<K> {
+    seq: i64,
+    kind: K,
 }
 
 #[derive(Serialize)]
@@ -134,62 +280,123 @@ enum ClientGameStateView<'a> {
     HelpOrContreToContract(ClientGameView<'a, HelpOrContreToContractState>),
     ContreDeclared(ClientGameView<'a, ContreDeclaredState>),
     Playing(ClientGameView<'a, PlayingState>),
+    AllPass(ClientGameView<'a, AllPassState>),
+}
+
+/// Who a view of the game is for.
+#[derive(Clone, Copy)]
+enum Viewer {
+    Seat(usize),
+    Spectator { all_hands: bool },
 }
 
 impl<'a> ClientGameStateView<'a> {
-    pub fn from_state_for_player(state: &'a GameState, player: usize) -> Self {
+    pub fn from_state_for(state: &'a GameState, viewer: Viewer, presence: &'a [Presence]) -> Self {
         use prefsty::core::game::GameState::*;
+
+        let exposed = state.exposed_hands();
         match state {
-            Bidding(game) => {
-                ClientGameStateView::Bidding(ClientGameView::from_state_for_player(&game, player))
-            }
+            Bidding(game) => ClientGameStateView::Bidding(ClientGameView::from_state_for(
+                game, viewer, &exposed, presence,
+            )),
             NoBidPlayClaim(game) => ClientGameStateView::NoBidPlayClaim(
-                ClientGameView::from_state_for_player(&game, player),
+                ClientGameView::from_state_for(game, viewer, &exposed, presence),
             ),
             NoBidPlayChoice(game) => ClientGameStateView::NoBidPlayChoice(
-                ClientGameView::from_state_for_player(&game, player),
+                ClientGameView::from_state_for(game, viewer, &exposed, presence),
             ),
             ChoosingCards(game) => ClientGameStateView::ChoosingCards(
-                ClientGameView::from_state_for_player(&game, player),
+                ClientGameView::from_state_for(game, viewer, &exposed, presence),
             ),
             ChoosingContract(game) => ClientGameStateView::ChoosingContract(
-                ClientGameView::from_state_for_player(&game, player),
+                ClientGameView::from_state_for(game, viewer, &exposed, presence),
             ),
             RespondingToContract(game) => ClientGameStateView::RespondingToContract(
-                ClientGameView::from_state_for_player(&game, player),
+                ClientGameView::from_state_for(game, viewer, &exposed, presence),
             ),
             HelpOrContreToContract(game) => ClientGameStateView::HelpOrContreToContract(
-                ClientGameView::from_state_for_player(&game, player),
+                ClientGameView::from_state_for(game, viewer, &exposed, presence),
             ),
             ContreDeclared(game) => ClientGameStateView::ContreDeclared(
-                ClientGameView::from_state_for_player(&game, player),
+                ClientGameView::from_state_for(game, viewer, &exposed, presence),
             ),
-            Playing(game) => {
-                ClientGameStateView::Playing(ClientGameView::from_state_for_player(&game, player))
-            }
+            Playing(game) => ClientGameStateView::Playing(ClientGameView::from_state_for(
+                game, viewer, &exposed, presence,
+            )),
+            AllPass(game) => ClientGameStateView::AllPass(ClientGameView::from_state_for(
+                game, viewer, &exposed, presence,
+            )),
         }
     }
 }
 
+/// A hand lying face up, shown to everyone at the table.
+#[derive(Serialize)]
+struct OpenHand {
+    seat: usize,
+    cards: CardSet,
+}
+
 #[derive(Serialize)]
 struct ClientGameView<'a, S> {
     pub state: &'a S,
+    pub rules: &'a Ruleset,
+    /// Hand position of the viewing seat, `None` while they deal and sit the hand out, and for
+    /// spectators.
+    pub position: Option<usize>,
+    pub dealer: usize,
     pub first: usize,
+    pub hand_number: usize,
     pub turn: usize,
-    pub hand: &'a Vec<Card>,
-    pub score: &'a [PlayerScore; 3],
+    pub hand: CardSet,
+    pub open_hands: Vec<OpenHand>,
+    pub score: &'a [PlayerScore],
     pub refas: &'a Refas,
+    /// Whether the player in each table seat is there.
+    pub presence: &'a [Presence],
 }
 
 impl<'a, S> ClientGameView<'a, S> {
-    pub fn from_state_for_player(game: &'a Game<S>, player: usize) -> Self {
+    pub fn from_state_for(
+        game: &'a Game<S>,
+        viewer: Viewer,
+        exposed: &[(usize, CardSet)],
+        presence: &'a [Presence],
+    ) -> Self {
+        let position = match viewer {
+            Viewer::Seat(seat) => game.position_of(seat),
+            Viewer::Spectator { .. } => None,
+        };
+        let open_hands = match viewer {
+            Viewer::Spectator { all_hands: true } => (0..HAND_PLAYERS)
+                .map(|p| OpenHand {
+                    seat: game.seat_of(p),
+                    cards: game.cards.hands[p],
+                })
+                .collect(),
+            _ => exposed
+                .iter()
+                .filter(|&&(p, _)| Some(p) != position)
+                .map(|&(p, cards)| OpenHand {
+                    seat: game.seat_of(p),
+                    cards,
+                })
+                .collect(),
+        };
+
         Self {
             state: &game.state,
+            rules: &game.rules,
+            position,
+            dealer: game.dealer,
             first: game.first,
+            hand_number: game.hand,
             turn: game.turn,
-            hand: &game.cards.hands[player],
+            hand: position.map_or(CardSet::EMPTY, |p| game.cards.hands[p]),
+            open_hands,
             score: &game.score,
             refas: &game.refas,
+            presence,
         }
     }
 }
@@ -197,61 +404,1049 @@ impl<'a, S> ClientGameView<'a, S> {
 #[derive(Serialize)]
 enum OutgoingMessageKind<'a> {
     State(ClientGameStateView<'a>),
+    /// Undo request waiting on the table, `None` once it is settled either way.
+    Undo(Option<&'a UndoRequest>),
+    /// The game moved on in its lifecycle, e.g. started, paused or finished.
+    Status(GameStatus),
+    /// Who sits where, sent whenever someone joins, leaves, changes seats or gets ready.
+    Seats(&'a [UserSafeIdx]),
+    Chat(&'a ChatMessage),
+    /// Recent chat, sent on connecting.
+    ChatHistory(Vec<&'a ChatMessage>),
+    /// How many people not seated at the table are watching.
+    Spectators(usize),
+    /// A seated player came, went or stepped away.
+    Presence {
+        seat: usize,
+        presence: Presence,
+    },
+    /// The request with this id went through.
+    Ack(&'a str),
+    /// The request with this id failed, and why.
+    Rejected {
+        id: &'a str,
+        error: String,
+    },
+    /// Something sent without an id failed.
     Error(String),
 }
 
+/// Handles a message. Ones with an id are answered with an ack or a rejection, and a repeat of
+/// one already handled within `REQUEST_WINDOW` gets the same answer without being handled again.
 async fn handle_message(
     bytes: Utf8Bytes,
+    connection: ConnectionId,
     user_id: UserId,
     game_id: GameId,
     state: &ApiContext,
 ) -> anyhow::Result<()> {
     let m: IncomingMessage = serde_json::from_str(bytes.as_str())?;
 
-    if let IncomingMessageKind::Game(action) = m.kind {
-        let game_repo: &GameRepo = &state.game_repo;
-        let mut game: model::Game = game_repo.get_by_id(game_id).await?;
-        let joined: Vec<UserSafeIdx> = game_repo.get_joined_by_game_id(game_id).await?;
+    if !matches!(m.kind, IncomingMessageKind::Resume { .. }) {
+        // anything else means the client isn't resuming
+        catch_up(state, connection, game_id, None).await?;
+    }
 
-        debug_assert!(game.id == game_id, "should be the same, just fetched");
+    let Some(id) = m.id else {
+        return handle_kind(m.kind, connection, user_id, game_id, state).await;
+    };
+    if id.len() > MAX_REQUEST_ID_LEN {
+        return Err(anyhow::anyhow!(
+            "request ids can't be longer than {MAX_REQUEST_ID_LEN} bytes"
+        ));
+    }
 
-        let player_idx = joined
-            .iter()
-            .find(|&u| u.id == user_id)
-            .ok_or(anyhow::anyhow!("user not in this game"))?
-            .idx;
+    let outcome = if m.kind.is_repeatable() {
+        let outcome = handle_kind(m.kind, connection, user_id, game_id, state).await;
+        Some(outcome.map_err(|err| err.to_string()))
+    } else {
+        handle_once(&id, m.kind, connection, user_id, game_id, state).await
+    };
 
-        debug_assert!(
-            player_idx >= 0 && player_idx <= 2,
-            "index should be in player range",
-        );
+    let kind = match outcome {
+        Some(Ok(())) => OutgoingMessageKind::Ack(&id),
+        Some(Err(error)) => OutgoingMessageKind::Rejected { id: &id, error },
+        None => return Ok(()),
+    };
+    send_direct(state, connection, kind);
 
-        game.state = game.state.apply(GameAction {
-            player: player_idx as usize,
-            kind: action,
-        })?;
+    Ok(())
+}
 
-        game_repo.update(&game).await?;
+/// Handles a request unless its id was seen before, in which case it's answered the way it was
+/// the first time. `None` while that one is still being handled, which a later retry learns the
+/// outcome of.
+async fn handle_once(
+    id: &str,
+    kind: IncomingMessageKind,
+    connection: ConnectionId,
+    user_id: UserId,
+    game_id: GameId,
+    state: &ApiContext,
+) -> Option<Result<(), String>> {
+    let request_repo = &state.request_repo;
+    match request_repo
+        .claim(
+            user_id,
+            id,
+            REQUEST_WINDOW.as_secs_f64(),
+            CLAIM_TIMEOUT.as_secs_f64(),
+        )
+        .await
+    {
+        Ok(true) => {}
+        Ok(false) => {
+            let request = request_repo.get(user_id, id).await.ok().flatten()?;
+            return request.done.then(|| request.error.map_or(Ok(()), Err));
+        }
+        Err(err) => return Some(Err(err.to_string())),
+    }
 
-        let client_game =
-            ClientGameStateView::from_state_for_player(&game.state, player_idx as usize);
+    // handlers only fail before what they change is saved, so this is whether it was
+    let outcome = handle_kind(kind, connection, user_id, game_id, state)
+        .await
+        .map_err(|err| err.to_string());
+    // left unsettled, repeats go unanswered until the claim times out
+    let _ = request_repo
+        .settle(user_id, id, outcome.as_ref().err().map(String::as_str))
+        .await;
 
-        let outgoing = serde_json::to_string(&OutgoingMessage {
-            kind: OutgoingMessageKind::State(client_game),
-        })
-        .unwrap();
+    Some(outcome)
+}
 
-        let joined_ids = joined.iter().map(|u| u.id);
-        for joined_id in joined_ids {
-            if let Some(client_tx) = state.clients.get(&(game_id, joined_id)) {
-                // if we fail this tough titties, someone else should
-                // notice client disconnected
-                let _ = client_tx.send(outgoing.clone().into());
+async fn handle_kind(
+    kind: IncomingMessageKind,
+    connection: ConnectionId,
+    user_id: UserId,
+    game_id: GameId,
+    state: &ApiContext,
+) -> anyhow::Result<()> {
+    match kind {
+        IncomingMessageKind::Game(action) => play(action, user_id, game_id, state).await,
+        IncomingMessageKind::RequestUndo => request_undo(user_id, game_id, state).await,
+        IncomingMessageKind::ApproveUndo => approve_undo(user_id, game_id, state).await,
+        IncomingMessageKind::DeclineUndo => decline_undo(user_id, game_id, state).await,
+        IncomingMessageKind::Chat(text) => chat(&text, user_id, game_id, state).await,
+        IncomingMessageKind::Mute(muted_id) => {
+            if muted_id == user_id {
+                return Err(anyhow::anyhow!("can't mute yourself"));
             }
+            Ok(state.chat_repo.mute(user_id, muted_id).await?)
+        }
+        IncomingMessageKind::Unmute(muted_id) => {
+            Ok(state.chat_repo.unmute(user_id, muted_id).await?)
         }
+        IncomingMessageKind::Sync => sync(connection, game_id, state).await,
+        IncomingMessageKind::Resume { last_seq } => {
+            catch_up(state, connection, game_id, Some(last_seq)).await
+        }
+        IncomingMessageKind::Away(away) => {
+            state.connection_repo.set_away(connection, away).await?;
+            broadcast_presence(state, game_id, user_id).await
+        }
+    }
+}
+
+async fn play(
+    action: GameActionKind,
+    user_id: UserId,
+    game_id: GameId,
+    state: &ApiContext,
+) -> anyhow::Result<()> {
+    let game_repo: &GameRepo = &state.game_repo;
+    let mut game: model::Game = game_repo.get_by_id(game_id).await?;
+    let joined: Vec<UserSafeIdx> = game_repo.get_joined_by_game_id(game_id).await?;
+
+    debug_assert!(game.id == game_id, "should be the same, just fetched");
+
+    let player_idx = seat_of(&joined, user_id)?;
+    in_progress(&game)?;
+    let game_state = game.state.take().expect("games in progress are dealt");
+
+    debug_assert!(
+        (0..game_state.rules().seats).contains(&player_idx),
+        "index should be in seat range",
+    );
+
+    let position = game_state
+        .position_of(player_idx)
+        .ok_or(anyhow::anyhow!("dealer sits this hand out"))?;
+
+    let action = GameAction {
+        player: position,
+        kind: action,
+    };
+    // Claims in ranked games are checked with a search that can take a while
+    let next = tokio::task::spawn_blocking({
+        let action = action.clone();
+        move || game_state.apply(action)
+    })
+    .await??;
+    if let Some(record) = &mut game.record {
+        record.push(action, &next);
+    }
+    let is_game_over = next.is_game_over();
+    game.state = Some(next);
+
+    let Some(dropped_undo) = game_repo.update(&game).await? else {
+        return Err(anyhow::anyhow!(
+            "the game changed before the move was saved"
+        ));
+    };
+    if dropped_undo {
+        broadcast(state, game_id, &joined, OutgoingMessageKind::Undo(None)).await;
+    }
+    broadcast_state(state, game_id).await;
+
+    if is_game_over {
+        // the move is saved, so failing to mark the game finished doesn't fail it
+        match game_repo
+            .set_status(game_id, GameStatus::InProgress, GameStatus::Finished)
+            .await
+        {
+            Ok(true) => {
+                broadcast_status(state, game_id, GameStatus::Finished).await;
+                lobby::publish(state, game_id, LobbyEventKind::Finished).await;
+            }
+            Ok(false) => {}
+            Err(err) => tracing::warn!("couldn't mark {game_id} finished: {err}"),
+        }
+    }
+
+    Ok(())
+}
+
+/// State of a game that is being played, an error while it waits, is paused or is over.
+fn in_progress(game: &model::Game) -> anyhow::Result<&GameState> {
+    match (&game.status, &game.state) {
+        (GameStatus::InProgress, Some(state)) => Ok(state),
+        (status, _) => Err(anyhow::anyhow!("game is {:?}", status)),
+    }
+}
+
+/// Seconds the other players have to agree to an undo.
+const UNDO_WINDOW: i64 = 30;
+
+async fn request_undo(user_id: UserId, game_id: GameId, state: &ApiContext) -> anyhow::Result<()> {
+    let game_repo: &GameRepo = &state.game_repo;
+    let game: model::Game = game_repo.get_by_id(game_id).await?;
+    let joined: Vec<UserSafeIdx> = game_repo.get_joined_by_game_id(game_id).await?;
+    let seat = seat_of(&joined, user_id)?;
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+
+    if !in_progress(&game)?.rules().allows_undo() {
+        return Err(anyhow::anyhow!("undo is not allowed in this game"));
+    }
+
+    // Check there is something to take back before bothering the table
+    let mut record = game
+        .record
+        .clone()
+        .ok_or(anyhow::anyhow!("game has no history to undo"))?;
+    if !record.take_back(seat) {
+        return Err(anyhow::anyhow!("nothing to undo"));
+    }
+
+    let pending = game_repo.get_undo(game_id).await?;
+    if pending.is_some_and(|undo| !undo.is_expired(now)) {
+        return Err(anyhow::anyhow!("an undo is already pending"));
+    }
+
+    let undo = UndoRequest {
+        seat,
+        approved: Vec::new(),
+        expires_at: now + UNDO_WINDOW,
+    };
+    if undo.is_approved(&joined) {
+        return take_back(state, game, &joined, seat).await;
+    }
+
+    game_repo.set_undo(game_id, Some(&undo)).await?;
+    broadcast(
+        state,
+        game_id,
+        &joined,
+        OutgoingMessageKind::Undo(Some(&undo)),
+    )
+    .await;
+
+    Ok(())
+}
+
+async fn approve_undo(user_id: UserId, game_id: GameId, state: &ApiContext) -> anyhow::Result<()> {
+    let game_repo: &GameRepo = &state.game_repo;
+    let joined: Vec<UserSafeIdx> = game_repo.get_joined_by_game_id(game_id).await?;
+    let seat = seat_of(&joined, user_id)?;
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+
+    // Approvals are added in one statement so that two at once both count. Only the one that
+    // completes the request sees it approved.
+    let undo = game_repo
+        .approve_undo(game_id, seat, now)
+        .await?
+        .ok_or(anyhow::anyhow!("no undo to approve"))?;
+    if undo.is_approved(&joined) {
+        let game: model::Game = game_repo.get_by_id(game_id).await?;
+        in_progress(&game)?;
+        return take_back(state, game, &joined, undo.seat).await;
+    }
+
+    broadcast(
+        state,
+        game_id,
+        &joined,
+        OutgoingMessageKind::Undo(Some(&undo)),
+    )
+    .await;
+
+    Ok(())
+}
+
+/// Any seated player can turn an undo down, the one who asked for it included.
+async fn decline_undo(user_id: UserId, game_id: GameId, state: &ApiContext) -> anyhow::Result<()> {
+    let game_repo: &GameRepo = &state.game_repo;
+    let joined: Vec<UserSafeIdx> = game_repo.get_joined_by_game_id(game_id).await?;
+    seat_of(&joined, user_id)?;
+
+    if game_repo.get_undo(game_id).await?.is_none() {
+        return Err(anyhow::anyhow!("no undo pending"));
+    }
+
+    game_repo.set_undo(game_id, None).await?;
+    broadcast(state, game_id, &joined, OutgoingMessageKind::Undo(None)).await;
+
+    Ok(())
+}
+
+/// Rolls the game back to before the last action of `seat` by replaying its shortened record.
+async fn take_back(
+    state: &ApiContext,
+    mut game: model::Game,
+    joined: &[UserSafeIdx],
+    seat: usize,
+) -> anyhow::Result<()> {
+    let record = game
+        .record
+        .as_mut()
+        .ok_or(anyhow::anyhow!("game has no history to undo"))?;
+    if !record.take_back(seat) {
+        return Err(anyhow::anyhow!("nothing to undo"));
+    }
+    game.state = Some(record.replay()?);
+
+    if state.game_repo.update(&game).await?.is_none() {
+        return Err(anyhow::anyhow!(
+            "the game changed before the undo was saved"
+        ));
+    }
+    broadcast(state, game.id, joined, OutgoingMessageKind::Undo(None)).await;
+    broadcast_state(state, game.id).await;
+
+    Ok(())
+}
+
+/// Longest chat message, in characters.
+const CHAT_MAX_LENGTH: usize = 500;
+/// Chat messages a user may send over `CHAT_WINDOW` seconds.
+const CHAT_RATE: i64 = 5;
+const CHAT_WINDOW: f64 = 10.0;
+/// Messages sent to clients as they connect.
+const CHAT_HISTORY: i64 = 100;
+
+async fn chat(
+    text: &str,
+    user_id: UserId,
+    game_id: GameId,
+    state: &ApiContext,
+) -> anyhow::Result<()> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err(anyhow::anyhow!("message is empty"));
+    }
+    if text.chars().count() > CHAT_MAX_LENGTH {
+        return Err(anyhow::anyhow!(
+            "messages are at most {} characters",
+            CHAT_MAX_LENGTH
+        ));
+    }
+
+    let chat_repo = &state.chat_repo;
+    let joined = state.game_repo.get_joined_by_game_id(game_id).await?;
+    let channel = if seat_of(&joined, user_id).is_ok() {
+        ChatChannel::Table
     } else {
-        return Err(anyhow::anyhow!("not implemented"));
+        ChatChannel::Spectators
+    };
+    let message = chat_repo
+        .push(game_id, user_id, channel, text, (CHAT_RATE, CHAT_WINDOW))
+        .await?
+        .ok_or(anyhow::anyhow!("sending messages too fast"))?;
+
+    let except = match channel {
+        ChatChannel::Table => Vec::new(),
+        ChatChannel::Spectators => joined.iter().map(|user| user.id).collect(),
+    };
+    let event = Event::Game {
+        game_id,
+        audience: Audience::except(except).sent_by(user_id),
+        kind: serde_json::to_value(OutgoingMessageKind::Chat(&message)).unwrap(),
+    };
+    fanout::publish(state, &event).await;
+
+    Ok(())
+}
+
+async fn send_chat_history(
+    state: &ApiContext,
+    connection: ConnectionId,
+    game_id: GameId,
+    user_id: UserId,
+) -> anyhow::Result<()> {
+    let chat_repo = &state.chat_repo;
+    let joined = state.game_repo.get_joined_by_game_id(game_id).await?;
+    let is_seated = seat_of(&joined, user_id).is_ok();
+    let muted = chat_repo.get_muted(user_id).await?;
+
+    let messages = chat_repo.get_recent(game_id, CHAT_HISTORY).await?;
+    let visible = messages
+        .iter()
+        .filter(|m| can_read(m.channel, is_seated) && !muted.contains(&m.user_id))
+        .collect();
+
+    send_direct(state, connection, OutgoingMessageKind::ChatHistory(visible));
+
+    Ok(())
+}
+
+/// Spectators read the players' chat, players don't read the spectators'.
+fn can_read(channel: ChatChannel, is_seated: bool) -> bool {
+    channel == ChatChannel::Table || !is_seated
+}
+
+fn seat_of(joined: &[UserSafeIdx], user_id: UserId) -> anyhow::Result<usize> {
+    joined
+        .iter()
+        .find(|&u| u.id == user_id)
+        .map(|u| u.idx as usize)
+        .ok_or(anyhow::anyhow!("user not in this game"))
+}
+
+/// Has everyone in the room, on every instance, sent their own view of the game.
+pub(crate) async fn broadcast_state(state: &ApiContext, game_id: GameId) {
+    fanout::publish(state, &Event::State(game_id)).await
+}
+
+/// Whether anyone on this instance is connected to the game.
+pub(crate) fn is_followed(state: &ApiContext, game_id: GameId) -> bool {
+    state.clients.iter().any(|client| client.game_id == game_id)
+}
+
+/// Sends an event published for a game to the connections here that are following along, and
+/// haven't been sent it yet, like the ones that caught up on it.
+pub(crate) async fn deliver(
+    state: &ApiContext,
+    game_id: GameId,
+    seq: i64,
+    event: &Event,
+) -> Result<(), DbError> {
+    send_event(state, game_id, seq, event, |_, client| {
+        !client.held && client.seq < seq
+    })
+    .await
+}
+
+/// Sends an event to the connections to the game `to` picks. States are sent as each connection
+/// sees them: seats get their own hand and the ones lying open, spectators what the game lets
+/// them see. Games that haven't been dealt yet have nothing to show.
+async fn send_event(
+    state: &ApiContext,
+    game_id: GameId,
+    seq: i64,
+    event: &Event,
+    to: impl Fn(&ConnectionId, &Client) -> bool,
+) -> Result<(), DbError> {
+    let is_target = |connection: &ConnectionId, client: &Client| {
+        client.game_id == game_id && to(connection, client)
+    };
+
+    match event {
+        Event::State(_) => {
+            if !state
+                .clients
+                .iter()
+                .any(|client| is_target(client.key(), client.value()))
+            {
+                return Ok(());
+            }
+
+            let game_repo = &state.game_repo;
+            let game = game_repo.get_by_id(game_id).await?;
+            let joined = game_repo.get_joined_by_game_id(game_id).await?;
+            let presence = presence_by_seat(state, &game, &joined).await?;
+
+            for mut client in state.clients.iter_mut() {
+                if is_target(client.key(), client.value()) {
+                    client.seq = seq;
+                    send_state(&client.tx, seq, &game, &joined, &presence, client.user_id);
+                }
+            }
+        }
+        Event::Game { audience, kind, .. } => {
+            let outgoing = serde_json::to_string(&OutgoingMessage { seq, kind })?;
+            let muted_by = match audience.sender() {
+                Some(sender) => {
+                    let here: Vec<UserId> = state
+                        .clients
+                        .iter()
+                        .filter(|client| is_target(client.key(), client.value()))
+                        .map(|client| client.user_id)
+                        .collect();
+                    state.chat_repo.get_muted_by(sender, &here).await?
+                }
+                None => Vec::new(),
+            };
+            for mut client in state.clients.iter_mut() {
+                if is_target(client.key(), client.value()) {
+                    client.seq = seq;
+                    if audience.includes(client.user_id) && !muted_by.contains(&client.user_id) {
+                        let _ = client.tx.send(outgoing.clone().into());
+                    }
+                }
+            }
+        }
+        Event::Lobby { .. } => {}
+    }
+
+    Ok(())
+}
+
+/// Sends a message to one connection, numbered like the last event it was sent. Returns whether
+/// the connection is still there.
+fn send_direct(state: &ApiContext, connection: ConnectionId, kind: OutgoingMessageKind) -> bool {
+    let Some(client) = state.clients.get(&connection) else {
+        return false;
+    };
+    let outgoing = serde_json::to_string(&OutgoingMessage {
+        seq: client.seq,
+        kind,
+    })
+    .unwrap();
+
+    client.tx.send(outgoing.into()).is_ok()
+}
+
+/// Sends a held connection the events it missed since `since`, or the chat and the events since
+/// it connected when it isn't resuming, and lets events through to it from then on. When some
+/// of those events aren't kept anymore, it gets everything there is to know about the game
+/// instead.
+async fn catch_up(
+    state: &ApiContext,
+    connection: ConnectionId,
+    game_id: GameId,
+    since: Option<i64>,
+) -> anyhow::Result<()> {
+    match state.clients.get(&connection).map(|client| client.held) {
+        Some(true) => {}
+        Some(false) if since.is_some() => {
+            return Err(anyhow::anyhow!("only a client's first message resumes"));
+        }
+        _ => return Ok(()),
+    }
+
+    let history = fanout::history_of(state, game_id);
+    let history = history.lock().await;
+    // caught up by someone else while waiting for the lock
+    let connected_at = match state.clients.get(&connection) {
+        Some(client) if client.held => client.seq,
+        _ => return Ok(()),
+    };
+    let is_resuming = since.is_some();
+    let since = since.unwrap_or(connected_at);
+
+    let missed = if since > connected_at {
+        // ahead of the game, so not from this game
+        None
+    } else if history.is_empty() {
+        // nothing happened since the client connected, which is fine if it was there before
+        (since == connected_at).then(Vec::new)
+    } else {
+        history.since(since)
+    };
+
+    match missed {
+        Some(events) => {
+            if !is_resuming {
+                let user_id = state.clients.get(&connection).map(|client| client.user_id);
+                if let Some(user_id) = user_id {
+                    send_chat_history(state, connection, game_id, user_id).await?;
+                }
+            }
+
+            // states are snapshots, so the last one is enough
+            let last_state = events
+                .iter()
+                .rposition(|(_, event)| matches!(event, Event::State(_)));
+            for (i, (seq, event)) in events.iter().enumerate() {
+                if matches!(event, Event::State(_)) && Some(i) != last_state {
+                    continue;
+                }
+                send_event(state, game_id, *seq, event, |&id, _| id == connection).await?;
+            }
+
+            let seq = events.last().map_or(since, |(seq, _)| *seq);
+            if let Some(mut client) = state.clients.get_mut(&connection) {
+                client.seq = seq;
+            }
+        }
+        None => full_sync(state, connection, game_id).await?,
+    }
+
+    if let Some(mut client) = state.clients.get_mut(&connection) {
+        client.held = false;
+    }
+    Ok(())
+}
+
+/// Sends a connection all there is to know about the game, as of its latest event.
+async fn full_sync(
+    state: &ApiContext,
+    connection: ConnectionId,
+    game_id: GameId,
+) -> anyhow::Result<()> {
+    let game_repo = &state.game_repo;
+    let seq = game_repo.get_seq(game_id).await?;
+    let game = game_repo.get_by_id(game_id).await?;
+    let joined = game_repo.get_joined_by_game_id(game_id).await?;
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    let undo = game_repo
+        .get_undo(game_id)
+        .await?
+        .filter(|undo| !undo.is_expired(now));
+    let connections = state.connection_repo.get_by_game_id(game_id).await?;
+    let presence = presence_by_seat(state, &game, &joined).await?;
+
+    match state.clients.get_mut(&connection) {
+        Some(mut client) => client.seq = seq,
+        None => return Ok(()),
+    }
+    send_direct(state, connection, OutgoingMessageKind::Status(game.status));
+    send_direct(state, connection, OutgoingMessageKind::Seats(&joined));
+    let spectators = spectators_of(&connections, &joined);
+    send_direct(
+        state,
+        connection,
+        OutgoingMessageKind::Spectators(spectators),
+    );
+    send_direct(state, connection, OutgoingMessageKind::Undo(undo.as_ref()));
+    let user_id = match state.clients.get(&connection) {
+        Some(client) => client.user_id,
+        None => return Ok(()),
+    };
+    send_chat_history(state, connection, game_id, user_id).await?;
+    if let Some(client) = state.clients.get(&connection) {
+        send_state(&client.tx, seq, &game, &joined, &presence, client.user_id);
+    }
+
+    Ok(())
+}
+
+/// Sends `user_id` their view of the game, spectators' views of all the hands only after the
+/// delay.
+fn send_state(
+    client_tx: &Outbox,
+    seq: i64,
+    game: &model::Game,
+    joined: &[UserSafeIdx],
+    presence: &[Presence],
+    user_id: UserId,
+) {
+    let Some(game_state) = &game.state else {
+        return;
+    };
+    let Some((viewer, delay)) = viewer_of(game, joined, user_id) else {
+        return;
+    };
+
+    let outgoing = serde_json::to_string(&OutgoingMessage {
+        seq,
+        kind: OutgoingMessageKind::State(ClientGameStateView::from_state_for(
+            game_state, viewer, presence,
+        )),
+    })
+    .unwrap();
+
+    if delay == 0 {
+        // if we fail this tough titties, someone else should
+        // notice client disconnected
+        let _ = client_tx.send_state(outgoing.into());
+        return;
+    }
+
+    let client_tx = client_tx.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_secs(delay)).await;
+        let _ = client_tx.send_state(outgoing.into());
+    });
+}
+
+/// How `user_id` sees the game and how many seconds late, `None` when they may not watch it.
+fn viewer_of(game: &model::Game, joined: &[UserSafeIdx], user_id: UserId) -> Option<(Viewer, u64)> {
+    match (seat_of(joined, user_id), game.spectators()) {
+        (Ok(seat), _) => Some((Viewer::Seat(seat), 0)),
+        (Err(_), Spectators::Disallowed) => None,
+        (Err(_), Spectators::Public) => Some((Viewer::Spectator { all_hands: false }, 0)),
+        (Err(_), Spectators::AllHands { delay }) => {
+            Some((Viewer::Spectator { all_hands: true }, delay))
+        }
+    }
+}
+
+/// `user_id`'s view of the game, the same the websocket sends them. Spectators of a table that
+/// shows all the hands late see none of them here, as there is no holding the view back.
+pub(crate) async fn view_for(
+    state: &ApiContext,
+    game: &model::Game,
+    user_id: UserId,
+) -> Result<Option<serde_json::Value>, DbError> {
+    let Some(game_state) = &game.state else {
+        return Ok(None);
+    };
+    let joined = state.game_repo.get_joined_by_game_id(game.id).await?;
+    let viewer = match viewer_of(game, &joined, user_id) {
+        Some((viewer, 0)) => viewer,
+        Some(_) => Viewer::Spectator { all_hands: false },
+        None => return Ok(None),
+    };
+
+    let presence = presence_by_seat(state, game, &joined).await?;
+    let view = ClientGameStateView::from_state_for(game_state, viewer, &presence);
+    Ok(Some(serde_json::to_value(view).unwrap()))
+}
+
+/// Sends the requesting connection its current view of the game.
+async fn sync(connection: ConnectionId, game_id: GameId, state: &ApiContext) -> anyhow::Result<()> {
+    let game_repo: &GameRepo = &state.game_repo;
+    let game: model::Game = game_repo.get_by_id(game_id).await?;
+    let joined: Vec<UserSafeIdx> = game_repo.get_joined_by_game_id(game_id).await?;
+
+    let presence = presence_by_seat(state, &game, &joined).await?;
+    if let Some(client) = state.clients.get(&connection) {
+        send_state(
+            &client.tx,
+            client.seq,
+            &game,
+            &joined,
+            &presence,
+            client.user_id,
+        );
     }
 
     Ok(())
 }
+
+/// Tells the room how many people are watching, which changes as spectators come and go.
+/// Someone watching from several tabs counts once.
+async fn broadcast_spectators(state: &ApiContext, game_id: GameId) -> anyhow::Result<()> {
+    let joined = state.game_repo.get_joined_by_game_id(game_id).await?;
+    let connections = state.connection_repo.get_by_game_id(game_id).await?;
+    let spectators = spectators_of(&connections, &joined);
+
+    broadcast_room(state, game_id, OutgoingMessageKind::Spectators(spectators)).await;
+    Ok(())
+}
+
+/// How many messages each game connection has waiting to be sent.
+pub(crate) fn queue_depths(state: &ApiContext) -> Vec<usize> {
+    state
+        .clients
+        .iter()
+        .map(|client| client.tx.depth())
+        .collect()
+}
+
+/// People connected to the game without a seat, however many connections they have.
+fn spectators_of(connections: &[Connection], joined: &[UserSafeIdx]) -> usize {
+    connections
+        .iter()
+        .filter(|connection| seat_of(joined, connection.user_id).is_err())
+        .map(|connection| connection.user_id)
+        .collect::<HashSet<_>>()
+        .len()
+}
+
+/// Online when any of the user's connections is, away when all of them are.
+fn presence_of(connections: &[Connection], user_id: UserId) -> Presence {
+    let mut presence = Presence::Offline;
+    for connection in connections {
+        if connection.user_id == user_id {
+            if !connection.away {
+                return Presence::Online;
+            }
+            presence = Presence::Away;
+        }
+    }
+    presence
+}
+
+/// Presence of every seat at the table, empty seats being offline.
+async fn presence_by_seat(
+    state: &ApiContext,
+    game: &model::Game,
+    joined: &[UserSafeIdx],
+) -> Result<Vec<Presence>, DbError> {
+    let connections = state.connection_repo.get_by_game_id(game.id).await?;
+    let mut presence = vec![Presence::Offline; game.rules().seats];
+    for user in joined {
+        presence[user.idx as usize] = presence_of(&connections, user.id);
+    }
+    Ok(presence)
+}
+
+/// Tells the room whether `user_id` is there, if they sit at the table.
+async fn broadcast_presence(
+    state: &ApiContext,
+    game_id: GameId,
+    user_id: UserId,
+) -> anyhow::Result<()> {
+    let joined = state.game_repo.get_joined_by_game_id(game_id).await?;
+    if let Ok(seat) = seat_of(&joined, user_id) {
+        let connections = state.connection_repo.get_by_game_id(game_id).await?;
+        let presence = presence_of(&connections, user_id);
+        broadcast_room(
+            state,
+            game_id,
+            OutgoingMessageKind::Presence { seat, presence },
+        )
+        .await;
+    }
+
+    Ok(())
+}
+
+/// Sends the new status to everyone in the room.
+pub(crate) async fn broadcast_status(state: &ApiContext, game_id: GameId, status: GameStatus) {
+    broadcast_room(state, game_id, OutgoingMessageKind::Status(status)).await
+}
+
+/// Sends the seating to everyone in the room, including those who haven't taken a seat yet.
+pub(crate) async fn broadcast_seats(state: &ApiContext, game_id: GameId, joined: &[UserSafeIdx]) {
+    broadcast_room(state, game_id, OutgoingMessageKind::Seats(joined)).await
+}
+
+/// Sends the same message to everyone connected to the game, seated or not.
+async fn broadcast_room(state: &ApiContext, game_id: GameId, kind: OutgoingMessageKind<'_>) {
+    let event = Event::Game {
+        game_id,
+        audience: Audience::everyone(),
+        kind: serde_json::to_value(kind).unwrap(),
+    };
+    fanout::publish(state, &event).await
+}
+
+/// Sends the same message to everyone seated, on each of their connections.
+async fn broadcast(
+    state: &ApiContext,
+    game_id: GameId,
+    joined: &[UserSafeIdx],
+    kind: OutgoingMessageKind<'_>,
+) {
+    let event = Event::Game {
+        game_id,
+        audience: Audience::only(joined.iter().map(|user| user.id).collect()),
+        kind: serde_json::to_value(kind).unwrap(),
+    };
+    fanout::publish(state, &event).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use sqlx::postgres::PgPoolOptions;
+
+    use super::*;
+    use crate::http::{
+        ApiConfig,
+        repos::model::{Game, User},
+    };
+
+    /// An instance on the test database, delivering what any instance publishes.
+    async fn instance(pool: &sqlx::PgPool) -> ApiContext {
+        let config = ApiConfig {
+            hmac_key: "test".into(),
+        };
+        let state = ApiContext::new(config, pool.clone());
+        let listener = fanout::listener(pool).await.unwrap();
+        tokio::spawn(fanout::deliver(state.clone(), listener));
+        state
+    }
+
+    async fn user(state: &ApiContext) -> UserId {
+        let id = UserId::new_v4();
+        let user = User {
+            id,
+            username: format!("test-{id}"),
+            password: String::new(),
+            email: format!("{id}@test"),
+        };
+        state.user_repo.create(user).await.unwrap();
+        id
+    }
+
+    /// A connection following `game_id`, as if it had just connected.
+    async fn follow(state: &ApiContext, game_id: GameId, user_id: UserId) -> OutboxReceiver {
+        let (tx, rx) = outbox::channel(&state.metrics);
+        let client = Client {
+            game_id,
+            user_id,
+            tx,
+            seq: state.game_repo.get_seq(game_id).await.unwrap(),
+            held: false,
+        };
+        state.clients.insert(ConnectionId::new_v4(), client);
+        rx
+    }
+
+    /// Kind of the next message sent, `None` when nothing comes for a while.
+    async fn next_kind(rx: &mut OutboxReceiver) -> Option<String> {
+        let msg = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .ok()??;
+        let msg: serde_json::Value = serde_json::from_str(&msg.into_text().unwrap()).unwrap();
+        msg["kind"]
+            .as_object()
+            .and_then(|kind| kind.keys().next().cloned())
+    }
+
+    fn connection(user_id: UserId, away: bool) -> Connection {
+        Connection { user_id, away }
+    }
+
+    #[test]
+    fn presence_tells_online_away_and_offline() {
+        let (user_id, other) = (UserId::new_v4(), UserId::new_v4());
+        let others = [connection(other, false)];
+        assert_eq!(presence_of(&others, user_id), Presence::Offline);
+
+        let online = [connection(other, true), connection(user_id, false)];
+        assert_eq!(presence_of(&online, user_id), Presence::Online);
+
+        let away = [connection(user_id, true), connection(other, false)];
+        assert_eq!(presence_of(&away, user_id), Presence::Away);
+    }
+
+    #[test]
+    fn users_count_once_however_many_connections_they_have() {
+        let (player, spectator) = (UserId::new_v4(), UserId::new_v4());
+        let joined = [UserSafeIdx {
+            id: player,
+            username: "player".into(),
+            idx: 0,
+            ready: false,
+            abort_vote: false,
+            swap_to: None,
+        }];
+        let connections = [
+            connection(player, false),
+            connection(spectator, false),
+            connection(spectator, true),
+        ];
+        assert_eq!(spectators_of(&connections, &joined), 1);
+
+        // Away only once every tab is
+        let tabs = [connection(player, true), connection(player, false)];
+        assert_eq!(presence_of(&tabs, player), Presence::Online);
+        let tabs = [connection(player, true), connection(player, true)];
+        assert_eq!(presence_of(&tabs, player), Presence::Away);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Postgres at DATABASE_URL"]
+    async fn every_connection_of_a_user_is_told() {
+        let url = std::env::var("DATABASE_URL").unwrap();
+        let pool = PgPoolOptions::new().connect(&url).await.unwrap();
+        let state = instance(&pool).await;
+        let user_id = user(&state).await;
+        let game = Game {
+            id: GameId::new_v4(),
+            status: GameStatus::Waiting,
+            settings: None,
+            state: None,
+            record: None,
+            resigned: None,
+            private: false,
+            created_by: user_id,
+            version: 0,
+        };
+        let game_id = game.id;
+        state.game_repo.create(game).await.unwrap();
+
+        let mut tabs = [
+            follow(&state, game_id, user_id).await,
+            follow(&state, game_id, user_id).await,
+        ];
+        broadcast_status(&state, game_id, GameStatus::Waiting).await;
+        for rx in &mut tabs {
+            assert_eq!(next_kind(rx).await.as_deref(), Some("Status"));
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Postgres at DATABASE_URL"]
+    async fn instances_deliver_what_the_others_publish() {
+        let url = std::env::var("DATABASE_URL").unwrap();
+        let pool = PgPoolOptions::new().connect(&url).await.unwrap();
+        let a = instance(&pool).await;
+        let b = instance(&pool).await;
+
+        let sender = user(&a).await;
+        let reader = user(&a).await;
+        let muter = user(&a).await;
+        a.chat_repo.mute(muter, sender).await.unwrap();
+        let game = Game {
+            id: GameId::new_v4(),
+            status: GameStatus::Waiting,
+            settings: None,
+            state: None,
+            record: None,
+            resigned: None,
+            private: false,
+            created_by: sender,
+            version: 0,
+        };
+        let game_id = game.id;
+        a.game_repo.create(game).await.unwrap();
+
+        let mut reader_rx = follow(&b, game_id, reader).await;
+        let mut muter_rx = follow(&b, game_id, muter).await;
+
+        chat("hello", sender, game_id, &a).await.unwrap();
+        assert_eq!(next_kind(&mut reader_rx).await.as_deref(), Some("Chat"));
+
+        broadcast_status(&a, game_id, GameStatus::Waiting).await;
+        assert_eq!(next_kind(&mut reader_rx).await.as_deref(), Some("Status"));
+        // the muted message was skipped, so the status comes first
+        assert_eq!(next_kind(&mut muter_rx).await.as_deref(), Some("Status"));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Postgres at DATABASE_URL"]
+    async fn unsettled_claims_time_out() {
+        let url = std::env::var("DATABASE_URL").unwrap();
+        let pool = PgPoolOptions::new().connect(&url).await.unwrap();
+        let state = instance(&pool).await;
+        let user_id = user(&state).await;
+        let requests = &state.request_repo;
+        let window = REQUEST_WINDOW.as_secs_f64();
+
+        assert!(requests.claim(user_id, "a", window, 60.0).await.unwrap());
+        assert!(!requests.claim(user_id, "a", window, 60.0).await.unwrap());
+        // the instance handling it is taken to be gone
+        assert!(requests.claim(user_id, "a", window, 0.0).await.unwrap());
+
+        requests.settle(user_id, "a", None).await.unwrap();
+        assert!(!requests.claim(user_id, "a", window, 0.0).await.unwrap());
+        let request = requests.get(user_id, "a").await.unwrap().unwrap();
+        assert!(request.done && request.error.is_none());
+    }
+}